@@ -0,0 +1,245 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operand {
+    Register(u16),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    Value(u16),
+}
+
+struct Instruction<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+// Reads back the syntax of the disassembler, plus labels:
+//   ; comment
+//   loop: LD V0, 0x01
+//   JP loop
+//   DW 0x1234
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = BTreeMap::new();
+    let mut instructions = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut line = line.split(';').next().unwrap().trim();
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(line_number, "invalid label"));
+            }
+            let address = origin as usize + 2 * instructions.len();
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                return Err(error(line_number, "duplicate label"));
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands = if operands.trim().is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(|operand| operand.trim()).collect()
+        };
+        instructions.push(Instruction {
+            line: line_number,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands: operands,
+        });
+    }
+
+    let mut program = Vec::new();
+    for instruction in instructions {
+        let operands = instruction
+            .operands
+            .iter()
+            .map(|operand| parse_operand(operand, &labels))
+            .collect::<Option<Vec<Operand>>>()
+            .ok_or_else(|| error(instruction.line, "invalid operand"))?;
+        let opcode = encode(&instruction.mnemonic, &operands).map_err(|message| error(instruction.line, message))?;
+        program.extend_from_slice(&opcode.to_be_bytes());
+    }
+    return Ok(program);
+}
+
+fn error(line: usize, message: &str) -> AsmError {
+    return AsmError {
+        line: line,
+        message: message.to_string(),
+    };
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    return chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+fn parse_operand(text: &str, labels: &BTreeMap<String, u16>) -> Option<Operand> {
+    let operand = match text.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        upper => {
+            if let Some(register) = upper.strip_prefix('V').filter(|register| register.len() == 1) {
+                return u16::from_str_radix(register, 16).ok().map(Operand::Register);
+            }
+            if let Some(&address) = labels.get(text) {
+                return Some(Operand::Value(address));
+            }
+            let value = match upper.strip_prefix("0X") {
+                Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                None => upper.parse::<u16>().ok(),
+            };
+            return value.map(Operand::Value);
+        }
+    };
+    return Some(operand);
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, &'static str> {
+    use Operand::*;
+
+    let nnn = |value: u16| if value <= 0xFFF { Ok(value) } else { Err("address out of range") };
+    let nn = |value: u16| if value <= 0xFF { Ok(value) } else { Err("byte out of range") };
+    let n = |value: u16| if value <= 0xF { Ok(value) } else { Err("nibble out of range") };
+    let xy = |high: u16, x: u16, y: u16, low: u16| high << 12 | x << 8 | y << 4 | low;
+
+    return match (mnemonic, operands) {
+        ("CLS", []) => Ok(0x00E0),
+        ("RET", []) => Ok(0x00EE),
+        ("SYS", [Value(address)]) => nnn(*address),
+        ("JP", [Value(address)]) => Ok(0x1000 | nnn(*address)?),
+        ("JP", [Register(0), Value(address)]) => Ok(0xB000 | nnn(*address)?),
+        ("CALL", [Value(address)]) => Ok(0x2000 | nnn(*address)?),
+        ("SE", [Register(x), Value(value)]) => Ok(0x3000 | x << 8 | nn(*value)?),
+        ("SNE", [Register(x), Value(value)]) => Ok(0x4000 | x << 8 | nn(*value)?),
+        ("SE", [Register(x), Register(y)]) => Ok(xy(0x5, *x, *y, 0x0)),
+        ("SNE", [Register(x), Register(y)]) => Ok(xy(0x9, *x, *y, 0x0)),
+        ("LD", [Register(x), Value(value)]) => Ok(0x6000 | x << 8 | nn(*value)?),
+        ("ADD", [Register(x), Value(value)]) => Ok(0x7000 | x << 8 | nn(*value)?),
+        ("LD", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x0)),
+        ("OR", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x1)),
+        ("AND", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x2)),
+        ("XOR", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x3)),
+        ("ADD", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x4)),
+        ("SUB", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x5)),
+        ("SHR", [Register(x)]) => Ok(xy(0x8, *x, 0, 0x6)),
+        ("SHR", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x6)),
+        ("SUBN", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0x7)),
+        ("SHL", [Register(x)]) => Ok(xy(0x8, *x, 0, 0xE)),
+        ("SHL", [Register(x), Register(y)]) => Ok(xy(0x8, *x, *y, 0xE)),
+        ("LD", [I, Value(address)]) => Ok(0xA000 | nnn(*address)?),
+        ("RND", [Register(x), Value(value)]) => Ok(0xC000 | x << 8 | nn(*value)?),
+        ("DRW", [Register(x), Register(y), Value(height)]) => Ok(xy(0xD, *x, *y, n(*height)?)),
+        ("SKP", [Register(x)]) => Ok(0xE09E | x << 8),
+        ("SKNP", [Register(x)]) => Ok(0xE0A1 | x << 8),
+        ("AUDIO", []) => Ok(0xF002),
+        ("LD", [Register(x), DelayTimer]) => Ok(0xF007 | x << 8),
+        ("LD", [Register(x), Key]) => Ok(0xF00A | x << 8),
+        ("LD", [DelayTimer, Register(x)]) => Ok(0xF015 | x << 8),
+        ("LD", [SoundTimer, Register(x)]) => Ok(0xF018 | x << 8),
+        ("ADD", [I, Register(x)]) => Ok(0xF01E | x << 8),
+        ("LD", [Font, Register(x)]) => Ok(0xF029 | x << 8),
+        ("LD", [Bcd, Register(x)]) => Ok(0xF033 | x << 8),
+        ("PITCH", [Register(x)]) => Ok(0xF03A | x << 8),
+        ("LD", [IndirectI, Register(x)]) => Ok(0xF055 | x << 8),
+        ("LD", [Register(x), IndirectI]) => Ok(0xF065 | x << 8),
+        ("DW", [Value(word)]) => Ok(*word),
+        _ => Err("unknown instruction"),
+    };
+}
+
+#[cfg(test)]
+mod asm_tests {
+    use super::assemble;
+    use crate::disasm::disassemble;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use test_case::test_case;
+
+    #[test_case(0x00E0)]
+    #[test_case(0x00EE)]
+    #[test_case(0x1228)]
+    #[test_case(0x2ABC)]
+    #[test_case(0x3A05)]
+    #[test_case(0x5120)]
+    #[test_case(0x6A42)]
+    #[test_case(0x8124)]
+    #[test_case(0x8106)]
+    #[test_case(0x8126)]
+    #[test_case(0x812E)]
+    #[test_case(0xA123)]
+    #[test_case(0xB300)]
+    #[test_case(0xC0FF)]
+    #[test_case(0xD015)]
+    #[test_case(0xE4A1)]
+    #[test_case(0xF002)]
+    #[test_case(0xF20A)]
+    #[test_case(0xF33A)]
+    #[test_case(0xF555)]
+    #[test_case(0xF665)]
+    #[test_case(0x5121)]
+    fn given_disassembled_opcode_when_assemble_should_encode_same_opcode(opcode: u16) {
+        let program = assemble(&disassemble(opcode), 0x200).unwrap();
+
+        assert_eq!(program, opcode.to_be_bytes());
+    }
+
+    #[test]
+    fn given_labels_when_assemble_should_resolve_addresses() {
+        let source = "\
+; count forever
+start: LD V0, 0
+loop:
+  ADD V0, 1 ; next
+  JP loop
+  CALL start
+";
+
+        let program = assemble(source, 0x200).unwrap();
+
+        assert_eq!(program, vec![0x60, 0x00, 0x70, 0x01, 0x12, 0x02, 0x22, 0x00]);
+    }
+
+    #[test_case("LD V0, 0x100", "line 1: byte out of range")]
+    #[test_case("NOP", "line 1: unknown instruction")]
+    #[test_case("JP nowhere", "line 1: invalid operand")]
+    #[test_case("a: CLS\na: CLS", "line 2: duplicate label")]
+    fn given_invalid_source_when_assemble_should_describe_error(source: &str, message: &str) {
+        let error = assemble(source, 0x200).unwrap_err();
+
+        assert_eq!(error.to_string(), message);
+    }
+}
@@ -0,0 +1,320 @@
+use super::driver::AudioSink;
+use super::timers::AudioPattern;
+use super::timers::PATTERN_BITS;
+use alloc::vec::Vec;
+use core::f64::consts::TAU;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    pub fn parse(text: &str) -> Option<Self> {
+        return match text {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        };
+    }
+
+    // Phase goes from 0 to 1 over one period, samples are between -1 and 1
+    fn sample(&self, phase: f64) -> f32 {
+        return match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => (4.0 * libm::fabs(phase - 0.5) - 1.0) as f32,
+            Waveform::Sine => libm::sin(TAU * phase) as f32,
+        };
+    }
+}
+
+// Software mixer rendering the tone and the XO-CHIP pattern as mono PCM samples between -1 and 1
+#[derive(Clone)]
+pub struct Mixer {
+    sample_rate: f64,
+    waveform: Waveform,
+    volume: f32,
+    frequency: f64,
+    pattern: Option<AudioPattern>,
+    phase: f64,
+    playing: bool,
+    played: u64,
+    length: Option<u64>,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        return Self {
+            sample_rate: sample_rate as f64,
+            waveform: Waveform::Square,
+            volume: 1.0,
+            frequency: 0.0,
+            pattern: None,
+            phase: 0.0,
+            playing: false,
+            played: 0,
+            length: None,
+        };
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        return self.sample_rate as u32;
+    }
+
+    // Only used for the beeper, patterns are always 1-bit square samples
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        return self.playing;
+    }
+
+    // A stopped tone still plays until it reached its emulated length
+    pub fn next_sample(&mut self) -> f32 {
+        if !self.playing || self.length.is_some_and(|length| self.played >= length) {
+            self.playing = false;
+            return 0.0;
+        }
+        self.played += 1;
+
+        let (value, step) = match self.pattern {
+            Some(pattern) => {
+                let bit = pattern.bit((self.phase * PATTERN_BITS as f64) as usize);
+                let value = if bit { 1.0 } else { -1.0 };
+                (value, pattern.rate() / PATTERN_BITS as f64 / self.sample_rate)
+            }
+            None => (self.waveform.sample(self.phase), self.frequency / self.sample_rate),
+        };
+        self.phase = (self.phase + step) % 1.0;
+        return self.volume * value;
+    }
+
+    pub fn render(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+impl AudioSink for Mixer {
+    fn tone_on(&mut self, frequency: u32) {
+        self.frequency = frequency as f64;
+        self.phase = 0.0;
+        self.playing = true;
+        self.played = 0;
+        self.length = None;
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.length = Some((length.as_secs_f64() * self.sample_rate) as u64);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.pattern = pattern;
+    }
+}
+
+// Renders the sound as 16-bit PCM in emulated time, so the recording does not depend on the host speed
+#[derive(Clone)]
+pub struct Recorder {
+    mixer: Mixer,
+    elapsed: Duration,
+    samples: Vec<i16>,
+}
+
+impl Recorder {
+    pub fn new(sample_rate: u32) -> Self {
+        return Self {
+            mixer: Mixer::new(sample_rate),
+            elapsed: Duration::ZERO,
+            samples: Vec::new(),
+        };
+    }
+
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        return &mut self.mixer;
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        return &self.samples;
+    }
+
+    // Mono 16-bit PCM WAV file
+    #[cfg(feature = "std")]
+    pub fn write_wav<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let sample_rate = self.mixer.sample_rate();
+        let data_size = 2 * self.samples.len() as u32;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(2 * sample_rate).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        return Ok(());
+    }
+}
+
+impl AudioSink for Recorder {
+    fn tone_on(&mut self, frequency: u32) {
+        self.mixer.tone_on(frequency);
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.mixer.tone_off(length);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.mixer.set_pattern(pattern);
+    }
+
+    // The sample count is computed from the total time, rounding does not accumulate
+    fn advance(&mut self, elapsed: Duration) {
+        self.elapsed += elapsed;
+        let count = self.elapsed.as_nanos() * self.mixer.sample_rate() as u128 / NANOS_PER_SEC;
+        while (self.samples.len() as u128) < count {
+            let sample = self.mixer.next_sample();
+            self.samples.push((sample * i16::MAX as f32) as i16);
+        }
+    }
+}
+
+#[cfg(test)]
+mod audio_tests {
+    use super::Mixer;
+    use super::Recorder;
+    use super::Waveform;
+    use crate::driver::AudioSink;
+    use crate::timers::AudioPattern;
+    #[cfg(feature = "std")]
+    use alloc::vec::Vec;
+    use core::time::Duration;
+
+    #[test]
+    fn given_square_tone_when_render_should_alternate_every_half_period() {
+        let mut mixer = Mixer::new(8);
+        mixer.tone_on(2);
+        let mut out = [0.0; 8];
+
+        mixer.render(&mut out);
+
+        assert_eq!(out, [1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn given_stopped_tone_when_render_should_play_its_length_then_silence() {
+        let mut mixer = Mixer::new(1000);
+        mixer.set_waveform(Waveform::Triangle);
+        mixer.set_volume(0.5);
+        mixer.tone_on(250);
+        mixer.tone_off(Duration::from_millis(3));
+        let mut out = [1.0; 5];
+
+        mixer.render(&mut out);
+
+        assert_eq!(out, [0.5, 0.0, -0.5, 0.0, 0.0]);
+        assert!(!mixer.is_playing());
+    }
+
+    #[test]
+    fn given_pattern_when_render_should_play_bits_at_pattern_rate() {
+        let mut mixer = Mixer::new(4000);
+        let mut buffer = [0; 16];
+        buffer[0] = 0b1010_0000;
+        mixer.set_pattern(Some(AudioPattern {
+            buffer: buffer,
+            pitch: 64,
+        }));
+        mixer.tone_on(800);
+        let mut out = [0.0; 4];
+
+        mixer.render(&mut out);
+
+        assert_eq!(out, [1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn given_sine_tone_when_render_should_start_at_zero() {
+        let mut mixer = Mixer::new(4);
+        mixer.set_waveform(Waveform::Sine);
+        mixer.tone_on(1);
+        let mut out = [0.0; 2];
+
+        mixer.render(&mut out);
+
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[1], 1.0);
+    }
+
+    #[test]
+    fn given_silent_mixer_when_render_should_output_zero() {
+        let mut mixer = Mixer::new(44100);
+        let mut out = [1.0; 16];
+
+        mixer.render(&mut out);
+
+        assert_eq!(out, [0.0; 16]);
+    }
+
+    #[test]
+    fn given_recorder_when_advance_should_render_samples_in_emulated_time() {
+        let mut recorder = Recorder::new(1000);
+        recorder.tone_on(250);
+
+        for _ in 0..3 {
+            recorder.advance(Duration::from_micros(1500));
+        }
+        recorder.tone_off(Duration::from_millis(5));
+        recorder.advance(Duration::from_millis(2));
+
+        assert_eq!(recorder.samples().len(), 6);
+        assert_eq!(recorder.samples()[..4], [i16::MAX, i16::MAX, -i16::MAX, -i16::MAX]);
+        assert_eq!(recorder.samples()[4..], [i16::MAX, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_recorder_when_write_wav_should_write_pcm_header_and_data() {
+        let mut recorder = Recorder::new(8000);
+        recorder.advance(Duration::from_millis(1));
+        let mut file = Vec::new();
+
+        recorder.write_wav(&mut file).unwrap();
+
+        assert_eq!(file.len(), 44 + 16);
+        assert_eq!(&file[0..4], b"RIFF");
+        assert_eq!(&file[8..16], b"WAVEfmt ");
+        assert_eq!(file[24..28], 8000u32.to_le_bytes());
+        assert_eq!(&file[36..40], b"data");
+        assert_eq!(file[40..44], 16u32.to_le_bytes());
+    }
+}
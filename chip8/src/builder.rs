@@ -0,0 +1,454 @@
+use super::cpu::Cpu;
+use super::cpu::MemoryLayout;
+use super::cpu::PresentMode;
+use super::cpu::Quirks;
+use super::cpu::FONTS;
+use super::cpu::FONT_SIZE;
+use super::driver::AudioSink;
+use super::driver::Keypad;
+use super::driver::VideoSink;
+use super::machine::Machine;
+use super::machine::CPU_FREQUENCY;
+use super::machine::TIMER_FREQUENCY;
+use super::threading::Ratio;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+const KB: usize = 1024;
+const ADDRESS_SPACE: usize = 64 * KB;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn quirks(&self) -> Quirks {
+        return match self {
+            Variant::Chip8 => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                logic_resets_vf: true,
+                ..Quirks::default()
+            },
+            Variant::SuperChip => Quirks {
+                jump_uses_vx: true,
+                ..Quirks::default()
+            },
+            Variant::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                wrap_sprites: true,
+                ..Quirks::default()
+            },
+        };
+    }
+
+    pub fn max_memory_size(&self) -> usize {
+        return match self {
+            Variant::Chip8 | Variant::SuperChip => 4 * KB,
+            Variant::XoChip => ADDRESS_SPACE,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    InvalidFrequency { name: &'static str, value: f64 },
+    InvalidInstructionsPerFrame,
+    InvalidFont { size: usize },
+    InvalidLayout(&'static str),
+    MemoryTooLarge { size: usize, max: usize, variant: Variant },
+    RomTooLarge { size: usize, available: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            BuildError::InvalidFrequency { name, value } => {
+                write!(f, "{} frequency must be a positive number of Hz, got {}", name, value)
+            }
+            BuildError::InvalidInstructionsPerFrame => {
+                write!(f, "instructions per frame must be at least 1")
+            }
+            BuildError::InvalidFont { size } => write!(
+                f,
+                "font set must hold 16 glyphs of 5 bytes ({} bytes), got {} bytes",
+                FONT_SIZE, size
+            ),
+            BuildError::InvalidLayout(message) => write!(f, "invalid memory layout: {}", message),
+            BuildError::MemoryTooLarge { size, max, variant } => write!(
+                f,
+                "{:?} supports at most {} bytes of memory, got {}",
+                variant, max, size
+            ),
+            BuildError::RomTooLarge { size, available } => write!(
+                f,
+                "rom is {} bytes but only {} bytes are available after the program start",
+                size, available
+            ),
+        };
+    }
+}
+
+pub struct Chip8Builder {
+    variant: Option<Variant>,
+    quirks: Option<Quirks>,
+    cpu_frequency: f64,
+    timer_frequency: f64,
+    instructions_per_frame: Option<u32>,
+    font: Vec<u8>,
+    layout: MemoryLayout,
+    seed: Option<u64>,
+    present_mode: PresentMode,
+    rom: Vec<u8>,
+    video: Option<Box<dyn VideoSink>>,
+    audio: Option<Box<dyn AudioSink>>,
+    keypad: Option<Box<dyn Keypad>>,
+}
+
+impl Chip8Builder {
+    pub fn new() -> Self {
+        return Self {
+            variant: None,
+            quirks: None,
+            cpu_frequency: CPU_FREQUENCY,
+            timer_frequency: TIMER_FREQUENCY,
+            instructions_per_frame: None,
+            font: FONTS.to_vec(),
+            layout: MemoryLayout::default(),
+            seed: None,
+            present_mode: PresentMode::default(),
+            rom: Vec::new(),
+            video: None,
+            audio: None,
+            keypad: None,
+        };
+    }
+
+    // Also selects the variant quirks, unless quirks are set explicitly
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        return self;
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        return self;
+    }
+
+    pub fn cpu_frequency(mut self, frequency: f64) -> Self {
+        self.cpu_frequency = frequency;
+        return self;
+    }
+
+    pub fn timer_frequency(mut self, frequency: f64) -> Self {
+        self.timer_frequency = frequency;
+        return self;
+    }
+
+    // Replaces the cpu frequency, see Machine::set_instructions_per_frame
+    pub fn instructions_per_frame(mut self, instructions: u32) -> Self {
+        self.instructions_per_frame = Some(instructions);
+        return self;
+    }
+
+    pub fn font(mut self, font: &[u8]) -> Self {
+        self.font = font.to_vec();
+        return self;
+    }
+
+    pub fn memory_layout(mut self, layout: MemoryLayout) -> Self {
+        self.layout = layout;
+        return self;
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        return self;
+    }
+
+    pub fn present_mode(mut self, mode: PresentMode) -> Self {
+        self.present_mode = mode;
+        return self;
+    }
+
+    pub fn rom(mut self, rom: Vec<u8>) -> Self {
+        self.rom = rom;
+        return self;
+    }
+
+    pub fn video(mut self, video: Box<dyn VideoSink>) -> Self {
+        self.video = Some(video);
+        return self;
+    }
+
+    pub fn audio(mut self, audio: Box<dyn AudioSink>) -> Self {
+        self.audio = Some(audio);
+        return self;
+    }
+
+    pub fn keypad(mut self, keypad: Box<dyn Keypad>) -> Self {
+        self.keypad = Some(keypad);
+        return self;
+    }
+
+    pub fn build(self) -> Result<Emulator, BuildError> {
+        self.validate()?;
+
+        let quirks = self
+            .quirks
+            .or_else(|| self.variant.map(|variant| variant.quirks()))
+            .unwrap_or_default();
+        let mut cpu = Cpu::with_layout(self.timer_frequency, self.layout, self.font);
+        cpu.set_quirks(quirks);
+        cpu.set_present_mode(self.present_mode);
+        if let Some(seed) = self.seed {
+            cpu.seed(seed);
+        }
+        cpu.load(self.rom);
+        let mut machine = Machine::with_cpu(cpu, self.cpu_frequency, self.timer_frequency);
+        machine.set_instructions_per_frame(self.instructions_per_frame);
+
+        return Ok(Emulator {
+            machine: machine,
+            video: self.video,
+            audio: self.audio,
+            keypad: self.keypad,
+        });
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        for (name, value) in [("cpu", self.cpu_frequency), ("timer", self.timer_frequency)] {
+            // Frequencies too small for a Ratio round down to 0 Hz and would divide by zero
+            if !value.is_finite() || value <= 0.0 || Ratio::from_f64(value).numerator() == 0 {
+                return Err(BuildError::InvalidFrequency { name: name, value: value });
+            }
+        }
+
+        if self.instructions_per_frame == Some(0) {
+            return Err(BuildError::InvalidInstructionsPerFrame);
+        }
+
+        if self.font.len() != FONT_SIZE {
+            return Err(BuildError::InvalidFont { size: self.font.len() });
+        }
+
+        let layout = self.layout;
+        if layout.memory_size > ADDRESS_SPACE {
+            return Err(BuildError::InvalidLayout("memory is larger than the 64 KB address space"));
+        }
+        if let Some(variant) = self.variant {
+            if layout.memory_size > variant.max_memory_size() {
+                return Err(BuildError::MemoryTooLarge {
+                    size: layout.memory_size,
+                    max: variant.max_memory_size(),
+                    variant: variant,
+                });
+            }
+        }
+        if layout.program_start >= layout.memory_size {
+            return Err(BuildError::InvalidLayout("program start is outside of memory"));
+        }
+        let font_end = layout.font_address + FONT_SIZE;
+        if font_end > layout.memory_size {
+            return Err(BuildError::InvalidLayout("font does not fit in memory"));
+        }
+        if layout.font_address < layout.program_start + self.rom.len() && layout.program_start < font_end {
+            return Err(BuildError::InvalidLayout("font overlaps the program area"));
+        }
+
+        let available = layout.memory_size - layout.program_start;
+        if self.rom.len() > available {
+            return Err(BuildError::RomTooLarge {
+                size: self.rom.len(),
+                available: available,
+            });
+        }
+        return Ok(());
+    }
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+// A machine together with the drivers it was built with
+pub struct Emulator {
+    machine: Machine,
+    video: Option<Box<dyn VideoSink>>,
+    audio: Option<Box<dyn AudioSink>>,
+    keypad: Option<Box<dyn Keypad>>,
+}
+
+impl Emulator {
+    pub fn machine(&self) -> &Machine {
+        return &self.machine;
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        return &mut self.machine;
+    }
+
+    pub fn into_machine(self) -> Machine {
+        return self.machine;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn run_for(&mut self, elapsed: Duration) {
+        self.run_with_drivers(|machine| machine.run_for(elapsed));
+    }
+
+    pub fn run_for_nanos(&mut self, elapsed: u64) {
+        self.run_with_drivers(|machine| machine.run_for_nanos(elapsed));
+    }
+
+    pub fn run_frame(&mut self) {
+        self.run_with_drivers(|machine| machine.run_frame());
+    }
+
+    // Reads the keypad, runs the machine, then plays and presents the result
+    fn run_with_drivers<F: FnOnce(&mut Machine)>(&mut self, run: F) {
+        if let Some(keypad) = self.keypad.as_mut() {
+            self.machine.read_keypad(keypad.as_mut());
+        }
+        run(&mut self.machine);
+        if let Some(audio) = self.audio.as_mut() {
+            self.machine.play(audio.as_mut());
+        }
+        if let Some(video) = self.video.as_mut() {
+            self.machine.present(video.as_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::BuildError;
+    use super::Chip8Builder;
+    use super::Variant;
+    use crate::cpu::MemoryLayout;
+    use crate::cpu::Quirks;
+    use crate::driver::VideoSink;
+    use crate::framebuffer::Framebuffer;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use core::cell::RefCell;
+
+    struct FakeVideo {
+        frames: usize,
+    }
+
+    impl VideoSink for FakeVideo {
+        fn fill_buffer(&mut self, _framebuffer: &Framebuffer) {
+            self.frames += 1;
+        }
+    }
+
+    #[test]
+    fn given_defaults_when_build_should_use_current_behavior() {
+        let emulator = Chip8Builder::new().build().unwrap();
+
+        let cpu = emulator.machine().cpu();
+        assert_eq!(cpu.quirks(), Quirks::default());
+        assert_eq!(cpu.layout(), MemoryLayout::default());
+        assert_eq!(cpu.program_counter(), 0x200);
+    }
+
+    #[test]
+    fn given_variant_when_build_should_select_quirks_unless_overridden() {
+        let chip8 = Chip8Builder::new().variant(Variant::Chip8).build().unwrap();
+        let custom = Chip8Builder::new()
+            .variant(Variant::Chip8)
+            .quirks(Quirks::default())
+            .build()
+            .unwrap();
+
+        assert_eq!(chip8.machine().cpu().quirks(), Variant::Chip8.quirks());
+        assert_eq!(custom.machine().cpu().quirks(), Quirks::default());
+    }
+
+    #[test]
+    fn given_layout_when_build_should_place_font_and_program() {
+        let layout = MemoryLayout {
+            memory_size: 0x1000,
+            font_address: 0x050,
+            program_start: 0x300,
+        };
+
+        let emulator = Chip8Builder::new().memory_layout(layout).rom(vec![0xAB]).build().unwrap();
+
+        let cpu = emulator.machine().cpu();
+        assert_eq!(cpu.read_memory(0x050), Some(0xF0));
+        assert_eq!(cpu.read_memory(0x300), Some(0xAB));
+        assert_eq!(cpu.program_counter(), 0x300);
+    }
+
+    #[test]
+    fn given_same_seed_when_run_should_draw_same_random_numbers() {
+        // RND V0, 0xFF ; JP 0x200
+        let rom = vec![0xC0, 0xFF, 0x12, 0x00];
+        let mut left = Chip8Builder::new().seed(7).rom(rom.clone()).build().unwrap().into_machine();
+        let mut right = Chip8Builder::new().seed(7).rom(rom).build().unwrap().into_machine();
+
+        for _i in 0..5 {
+            left.run_frame();
+            right.run_frame();
+            assert_eq!(left.cpu().v(0), right.cpu().v(0));
+        }
+    }
+
+    #[test]
+    fn given_video_driver_when_run_should_present_frames() {
+        let video = Rc::new(RefCell::new(FakeVideo { frames: 0 }));
+        let mut emulator = Chip8Builder::new()
+            .rom(vec![0x12, 0x00])
+            .video(Box::new(video.clone()))
+            .build()
+            .unwrap();
+
+        emulator.run_for_nanos(1_000_000);
+
+        assert_eq!(video.borrow().frames, 1);
+    }
+
+    #[test]
+    fn given_invalid_settings_when_build_should_describe_error() {
+        let frequency = Chip8Builder::new().cpu_frequency(0.0).build().err().unwrap();
+        let font = Chip8Builder::new().font(&[0xF0; 10]).build().err().unwrap();
+        let memory = Chip8Builder::new()
+            .variant(Variant::SuperChip)
+            .memory_layout(MemoryLayout {
+                memory_size: 0x10000,
+                ..MemoryLayout::default()
+            })
+            .build()
+            .err()
+            .unwrap();
+        let rom = Chip8Builder::new().rom(vec![0; 0xE01]).build().err().unwrap();
+        let instructions = Chip8Builder::new().instructions_per_frame(0).build().err().unwrap();
+        let tiny = Chip8Builder::new().timer_frequency(1e-9).build().err().unwrap();
+
+        assert_eq!(frequency.to_string(), "cpu frequency must be a positive number of Hz, got 0");
+        assert_eq!(font, BuildError::InvalidFont { size: 10 });
+        assert_eq!(memory.to_string(), "SuperChip supports at most 4096 bytes of memory, got 65536");
+        assert_eq!(
+            rom,
+            BuildError::RomTooLarge {
+                size: 0xE01,
+                available: 0xE00
+            }
+        );
+        assert_eq!(instructions, BuildError::InvalidInstructionsPerFrame);
+        assert_eq!(tiny, BuildError::InvalidFrequency { name: "timer", value: 1e-9 });
+    }
+}
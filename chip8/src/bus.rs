@@ -0,0 +1,182 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Access {
+    Fetch,
+    Sprite,
+    Data,
+    Load,
+}
+
+pub trait MemoryObserver {
+    fn on_read(&mut self, _address: u16, _value: u8, _access: Access) {}
+    fn on_write(&mut self, _address: u16, _value: u8, _access: Access) {}
+}
+
+#[cfg(feature = "std")]
+impl<T: MemoryObserver> MemoryObserver for Arc<Mutex<T>> {
+    fn on_read(&mut self, address: u16, value: u8, access: Access) {
+        self.lock().unwrap().on_read(address, value, access);
+    }
+
+    fn on_write(&mut self, address: u16, value: u8, access: Access) {
+        self.lock().unwrap().on_write(address, value, access);
+    }
+}
+
+pub struct Bus {
+    memory: Vec<u8>,
+    observers: Vec<Box<dyn MemoryObserver + Send>>,
+}
+
+// Observers stay attached to the original bus, a clone only copies the memory
+impl Clone for Bus {
+    fn clone(&self) -> Self {
+        return Self {
+            memory: self.memory.clone(),
+            observers: Vec::new(),
+        };
+    }
+}
+
+impl Bus {
+    pub fn new(size: usize) -> Self {
+        return Self {
+            memory: vec![0; size],
+            observers: Vec::new(),
+        };
+    }
+
+    pub fn attach_observer(&mut self, observer: Box<dyn MemoryObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.memory.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.memory.is_empty();
+    }
+
+    // Without observers, an access costs a single never taken branch
+    #[inline]
+    pub fn read(&mut self, address: usize, access: Access) -> u8 {
+        let value = self.memory[address];
+        if !self.observers.is_empty() {
+            self.notify_read(address, value, access);
+        }
+        return value;
+    }
+
+    #[inline]
+    pub fn write(&mut self, address: usize, value: u8, access: Access) {
+        self.memory[address] = value;
+        if !self.observers.is_empty() {
+            self.notify_write(address, value, access);
+        }
+    }
+
+    // Debugger accesses are not program traffic, observers are not notified
+    pub fn peek(&self, address: usize) -> Option<u8> {
+        return self.memory.get(address).copied();
+    }
+
+    pub fn poke(&mut self, address: usize, value: u8) -> bool {
+        if address >= self.memory.len() {
+            return false;
+        }
+        self.memory[address] = value;
+        return true;
+    }
+
+    pub fn clear(&mut self) {
+        self.memory.iter_mut().for_each(|value| *value = 0);
+    }
+
+    #[cold]
+    fn notify_read(&mut self, address: usize, value: u8, access: Access) {
+        for observer in self.observers.iter_mut() {
+            observer.on_read(address as u16, value, access);
+        }
+    }
+
+    #[cold]
+    fn notify_write(&mut self, address: usize, value: u8, access: Access) {
+        for observer in self.observers.iter_mut() {
+            observer.on_write(address as u16, value, access);
+        }
+    }
+}
+
+// The observers are shared through Arc<Mutex>, which needs std
+#[cfg(all(test, feature = "std"))]
+mod bus_tests {
+    use super::Access;
+    use super::Bus;
+    use super::MemoryObserver;
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    struct FakeObserver {
+        reads: Vec<(u16, u8, Access)>,
+        writes: Vec<(u16, u8, Access)>,
+    }
+
+    impl FakeObserver {
+        fn new() -> Self {
+            return Self {
+                reads: Vec::new(),
+                writes: Vec::new(),
+            };
+        }
+    }
+
+    impl MemoryObserver for FakeObserver {
+        fn on_read(&mut self, address: u16, value: u8, access: Access) {
+            self.reads.push((address, value, access));
+        }
+
+        fn on_write(&mut self, address: u16, value: u8, access: Access) {
+            self.writes.push((address, value, access));
+        }
+    }
+
+    #[test]
+    fn given_observer_when_write_and_read_should_notify() {
+        let observer = Arc::new(Mutex::new(FakeObserver::new()));
+        let mut bus = Bus::new(16);
+        bus.attach_observer(Box::new(observer.clone()));
+
+        bus.write(3, 0x2A, Access::Data);
+        let value = bus.read(3, Access::Fetch);
+
+        assert_eq!(value, 0x2A);
+        assert_eq!(observer.lock().unwrap().writes, vec![(3, 0x2A, Access::Data)]);
+        assert_eq!(observer.lock().unwrap().reads, vec![(3, 0x2A, Access::Fetch)]);
+    }
+
+    #[test]
+    fn given_observer_when_peek_and_poke_should_not_notify() {
+        let observer = Arc::new(Mutex::new(FakeObserver::new()));
+        let mut bus = Bus::new(16);
+        bus.attach_observer(Box::new(observer.clone()));
+
+        assert!(bus.poke(3, 0x2A));
+        assert!(!bus.poke(16, 0x2A));
+
+        assert_eq!(bus.peek(3), Some(0x2A));
+        assert_eq!(bus.peek(16), None);
+        assert!(observer.lock().unwrap().reads.is_empty());
+        assert!(observer.lock().unwrap().writes.is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+use super::cpu::Cpu;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+
+const REGISTER_COUNT: u8 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CheatTarget {
+    Memory(u16),
+    Register(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CheatCode {
+    Freeze(CheatTarget, u8),
+    Poke(CheatTarget, u8),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<CheatCode>,
+    enabled: bool,
+    applied: bool,
+}
+
+impl Cheat {
+    pub fn new(name: &str, codes: Vec<CheatCode>) -> Self {
+        return Self {
+            name: name.to_string(),
+            codes: codes,
+            enabled: false,
+            applied: false,
+        };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.enabled;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheatError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+pub struct CheatEngine {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatEngine {
+    pub fn new() -> Self {
+        return Self { cheats: Vec::new() };
+    }
+
+    // Cheat file format:
+    //   # comment
+    //   [Infinite lives]
+    //   freeze 0x2F1 0x03
+    //   poke v5 0x05
+    pub fn parse(text: &str) -> Result<Self, CheatError> {
+        let mut engine = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                engine.add(Cheat::new(name.trim(), Vec::new()));
+                continue;
+            }
+
+            let error = |message: &str| CheatError {
+                line: line_number,
+                message: message.to_string(),
+            };
+            let cheat = engine
+                .cheats
+                .last_mut()
+                .ok_or_else(|| error("code outside of a [cheat] section"))?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(error("expected '<freeze|poke> <target> <value>'"));
+            }
+            let target = parse_target(fields[1]).ok_or_else(|| error("invalid target"))?;
+            let value = parse_number(fields[2])
+                .filter(|&value| value <= u8::MAX as usize)
+                .ok_or_else(|| error("invalid value"))? as u8;
+            let code = match fields[0] {
+                "freeze" => CheatCode::Freeze(target, value),
+                "poke" => CheatCode::Poke(target, value),
+                _ => return Err(error("unknown code, expected 'freeze' or 'poke'")),
+            };
+            cheat.codes.push(code);
+        }
+
+        return Ok(engine);
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        return &self.cheats;
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        let cheat = &mut self.cheats[index];
+        cheat.enabled = enabled;
+        cheat.applied = false;
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        let enabled = !self.cheats[index].enabled;
+        self.set_enabled(index, enabled);
+    }
+
+    // Called once per frame: freezes are written every time, pokes once after being enabled
+    pub fn apply(&mut self, cpu: &mut Cpu) {
+        for cheat in self.cheats.iter_mut().filter(|cheat| cheat.enabled) {
+            for code in cheat.codes.iter() {
+                match code {
+                    CheatCode::Freeze(target, value) => write_target(cpu, *target, *value),
+                    CheatCode::Poke(target, value) if !cheat.applied => write_target(cpu, *target, *value),
+                    CheatCode::Poke(_, _) => {}
+                }
+            }
+            cheat.applied = true;
+        }
+    }
+}
+
+impl Default for CheatEngine {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl fmt::Display for CheatEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cheat in self.cheats.iter() {
+            writeln!(f, "[{}]", cheat.name)?;
+            for code in cheat.codes.iter() {
+                let (kind, target, value) = match code {
+                    CheatCode::Freeze(target, value) => ("freeze", target, value),
+                    CheatCode::Poke(target, value) => ("poke", target, value),
+                };
+                match target {
+                    CheatTarget::Memory(address) => writeln!(f, "{} 0x{:03X} 0x{:02X}", kind, address, value)?,
+                    CheatTarget::Register(index) => writeln!(f, "{} v{:x} 0x{:02X}", kind, index, value)?,
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SearchCondition {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+pub struct MemorySearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl MemorySearch {
+    pub fn new(cpu: &Cpu) -> Self {
+        let snapshot = take_snapshot(cpu);
+        // 64 KB of memory has 0x10000 addresses, one more than the u16 range end
+        let candidates = (0..snapshot.len()).map(|address| address as u16).collect();
+        return Self {
+            snapshot: snapshot,
+            candidates: candidates,
+        };
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        return &self.candidates;
+    }
+
+    pub fn filter(&mut self, cpu: &Cpu, condition: SearchCondition) {
+        let snapshot = take_snapshot(cpu);
+        self.candidates.retain(|&address| {
+            let old = self.snapshot[address as usize];
+            let new = snapshot[address as usize];
+            return match condition {
+                SearchCondition::Equal(value) => new == value,
+                SearchCondition::Changed => new != old,
+                SearchCondition::Unchanged => new == old,
+                SearchCondition::Increased => new > old,
+                SearchCondition::Decreased => new < old,
+            };
+        });
+        self.snapshot = snapshot;
+    }
+}
+
+pub fn parse_target(text: &str) -> Option<CheatTarget> {
+    let text = text.to_lowercase();
+    if let Some(index) = text.strip_prefix('v') {
+        return u8::from_str_radix(index, 16)
+            .ok()
+            .filter(|&index| index < REGISTER_COUNT)
+            .map(CheatTarget::Register);
+    }
+    return parse_number(&text)
+        .and_then(|address| u16::try_from(address).ok())
+        .map(CheatTarget::Memory);
+}
+
+pub fn write_target(cpu: &mut Cpu, target: CheatTarget, value: u8) {
+    match target {
+        CheatTarget::Memory(address) => {
+            cpu.write_memory(address as usize, value);
+        }
+        CheatTarget::Register(index) => cpu.set_v(index as usize, value),
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    return match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse::<usize>().ok(),
+    };
+}
+
+fn take_snapshot(cpu: &Cpu) -> Vec<u8> {
+    return (0..cpu.memory_size())
+        .map(|address| cpu.read_memory(address).unwrap())
+        .collect();
+}
+
+#[cfg(test)]
+mod cheats_tests {
+    use super::CheatCode;
+    use super::CheatEngine;
+    use super::CheatTarget;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    const CHEATS: &str = "\
+# Blitz
+[Infinite lives]
+freeze 0x2F1 0x03
+
+[Fast start]
+poke v5 0x05
+poke 0x300 12
+";
+
+    #[test]
+    fn given_cheat_file_when_parse_should_read_cheats() {
+        let engine = CheatEngine::parse(CHEATS).unwrap();
+
+        let cheats = engine.cheats();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].name, "Infinite lives");
+        assert_eq!(cheats[0].codes, vec![CheatCode::Freeze(CheatTarget::Memory(0x2F1), 0x03)]);
+        assert_eq!(
+            cheats[1].codes,
+            vec![
+                CheatCode::Poke(CheatTarget::Register(5), 0x05),
+                CheatCode::Poke(CheatTarget::Memory(0x300), 12)
+            ]
+        );
+        assert!(!cheats[0].is_enabled());
+    }
+
+    #[test]
+    fn given_engine_when_format_should_parse_back() {
+        let engine = CheatEngine::parse(CHEATS).unwrap();
+
+        let parsed = CheatEngine::parse(&engine.to_string()).unwrap();
+
+        assert_eq!(parsed.cheats(), engine.cheats());
+    }
+
+    #[test]
+    fn given_code_without_section_when_parse_should_fail() {
+        let error = CheatEngine::parse("freeze 0x200 1").err().unwrap();
+
+        assert_eq!(error.line, 1);
+    }
+
+    #[test]
+    fn given_invalid_value_when_parse_should_report_line() {
+        let error = CheatEngine::parse("[Lives]\nfreeze 0x200 0x100").err().unwrap();
+
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "invalid value");
+    }
+}
@@ -1,2 +1,4 @@
 pub const X_SIZE: usize = 64;
 pub const Y_SIZE: usize = 32;
+// Keys on the hexadecimal keypad, 0 to F
+pub const KEY_COUNT: usize = 16;
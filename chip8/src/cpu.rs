@@ -1,11 +1,27 @@
+use super::bus::Access;
+use super::bus::Bus;
+use super::bus::MemoryObserver;
 use super::constants::*;
-use super::driver::Driver;
+use super::framebuffer::Framebuffer;
 use super::threading::Processor;
+use super::threading::Processors;
 use super::timers::*;
-use std::cell::RefCell;
-use std::rc::Rc;
-
-const FONTS: [u8; 80] = [
+use super::trace::TraceEntry;
+use super::trace::Tracer;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+use rand::rngs::SmallRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+pub const FONT_SIZE: usize = 80;
+
+pub const FONTS: [u8; FONT_SIZE] = [
     0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
@@ -29,6 +45,86 @@ const V_SIZE: usize = 16;
 const STACK_SIZE: usize = 16;
 const MEMORY_SIZE: usize = 4 * KB;
 const PROGRAM_START: usize = 0x200;
+const FONT_ADDRESS: usize = 0x000;
+const GLYPH_SIZE: u16 = 5;
+const PLANES: usize = 1;
+const SPRITE_SHIFT: u32 = 56;
+#[cfg(not(feature = "std"))]
+const DEFAULT_SEED: u64 = 0xC8;
+
+// Thread indexes when the Cpu is scheduled as a set of processors
+pub const CPU_THREAD: usize = 0;
+pub const DELAY_TIMER_THREAD: usize = 1;
+pub const SOUND_TIMER_THREAD: usize = 2;
+pub const FRAME_THREAD: usize = 3;
+pub const THREAD_COUNT: usize = 4;
+
+// Frame waits for the 60 Hz tick and presents whole frames, Immediate presents after every CLS and DRW
+// NonErasing also presents at the tick, the display as it was after the last DRW that erased no pixel,
+// so that sprites erased to be moved are not shown missing
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PresentMode {
+    #[default]
+    Frame,
+    Immediate,
+    NonErasing,
+}
+
+// Behaviors that differ between interpreters, all disabled by default
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,
+    pub load_store_increments_i: bool,
+    pub jump_uses_vx: bool,
+    pub logic_resets_vf: bool,
+    pub wrap_sprites: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemoryLayout {
+    pub memory_size: usize,
+    pub font_address: usize,
+    pub program_start: usize,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        return Self {
+            memory_size: MEMORY_SIZE,
+            font_address: FONT_ADDRESS,
+            program_start: PROGRAM_START,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HaltReason {
+    Request,
+    Breakpoint(u16),
+    Watchpoint { address: u16, old: u8, new: u8 },
+}
+
+// Register writes from a debugger that would crash the next instruction
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RegisterError {
+    ProgramCounterOutOfMemory { value: u16, memory_size: usize },
+    StackPointerOverflow { value: u16 },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            RegisterError::ProgramCounterOutOfMemory { value, memory_size } => write!(
+                f,
+                "pc 0x{:X} is outside of memory, the instruction must end before 0x{:X}",
+                value, memory_size
+            ),
+            RegisterError::StackPointerOverflow { value } => {
+                write!(f, "sp {} is larger than the stack size {}", value, STACK_SIZE)
+            }
+        };
+    }
+}
 
 pub struct Cpu {
     i: u16,
@@ -36,34 +132,63 @@ pub struct Cpu {
     program_counter: usize,
     stack: Vec<u16>,
     stack_pointer: u16,
-    pub display: Vec<Vec<usize>>, // TODO: pub only for cpu_test !!!
-    memory: Vec<u8>,
-    delay_timer: Rc<RefCell<CpuTimer>>,
-    sound_timer: Rc<RefCell<SoundTimer>>,
-    driver: Rc<RefCell<dyn Driver>>,
+    display: Framebuffer,
+    frame: Framebuffer,
+    present_mode: PresentMode,
+    frame_count: u64,
+    frame_ready: bool,
+    frame_started: bool,
+    memory: Bus,
+    layout: MemoryLayout,
+    font: Vec<u8>,
+    quirks: Quirks,
+    delay_timer: CpuTimer,
+    sound_timer: SoundTimer,
+    keys: [bool; KEY_COUNT],
+    rng: SmallRng,
     current_opcode: u16,
     rom: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeMap<u16, u8>,
+    halt_reason: Option<HaltReason>,
+    skip_breakpoint: bool,
+    tracers: Vec<Box<dyn Tracer + Send>>,
 }
 
 impl Cpu {
-    pub fn new(
-        delay_timer: Rc<RefCell<CpuTimer>>,
-        sound_timer: Rc<RefCell<SoundTimer>>,
-        driver: Rc<RefCell<dyn Driver>>,
-    ) -> Self {
+    pub fn new(timer_frequency: f64) -> Self {
+        return Self::with_layout(timer_frequency, MemoryLayout::default(), FONTS.to_vec());
+    }
+
+    // The font and the rom must fit in the layout, see Chip8Builder for a validated setup
+    pub fn with_layout(timer_frequency: f64, layout: MemoryLayout, font: Vec<u8>) -> Self {
         let mut cpu = Self {
             i: 0,
             v: vec![0; V_SIZE],
-            program_counter: PROGRAM_START,
+            program_counter: layout.program_start,
             stack: vec![0; STACK_SIZE],
             stack_pointer: 0,
-            display: vec![vec![0; Y_SIZE]; X_SIZE],
-            memory: vec![0; MEMORY_SIZE],
-            delay_timer: delay_timer,
-            sound_timer: sound_timer,
-            driver: driver,
+            display: Framebuffer::new(X_SIZE, Y_SIZE, PLANES),
+            frame: Framebuffer::new(X_SIZE, Y_SIZE, PLANES),
+            present_mode: PresentMode::default(),
+            frame_count: 0,
+            frame_ready: false,
+            frame_started: false,
+            memory: Bus::new(layout.memory_size),
+            layout: layout,
+            font: font,
+            quirks: Quirks::default(),
+            delay_timer: CpuTimer::new(),
+            sound_timer: SoundTimer::new(timer_frequency),
+            keys: [false; KEY_COUNT],
+            rng: new_rng(),
             current_opcode: 0,
             rom: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            halt_reason: None,
+            skip_breakpoint: false,
+            tracers: Vec::new(),
         };
         cpu.initialize_memory();
         return cpu;
@@ -74,23 +199,268 @@ impl Cpu {
         self.reset();
     }
 
+    pub fn v(&self, index: usize) -> u8 {
+        return self.v[index];
+    }
+
+    pub fn set_v(&mut self, index: usize, value: u8) {
+        self.v[index] = value;
+    }
+
+    pub fn i(&self) -> u16 {
+        return self.i;
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.i = value;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        return self.program_counter as u16;
+    }
+
+    // The two bytes of the next instruction must be in memory
+    pub fn set_program_counter(&mut self, value: u16) -> Result<(), RegisterError> {
+        if value as usize + 1 >= self.memory_size() {
+            return Err(RegisterError::ProgramCounterOutOfMemory {
+                value: value,
+                memory_size: self.memory_size(),
+            });
+        }
+        self.program_counter = value as usize;
+        return Ok(());
+    }
+
+    pub fn stack_pointer(&self) -> u16 {
+        return self.stack_pointer;
+    }
+
+    pub fn set_stack_pointer(&mut self, value: u16) -> Result<(), RegisterError> {
+        if value as usize > STACK_SIZE {
+            return Err(RegisterError::StackPointerOverflow { value: value });
+        }
+        self.stack_pointer = value;
+        return Ok(());
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        return &self.stack[..self.stack_pointer as usize];
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        return self.delay_timer.value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        return self.sound_timer.value();
+    }
+
+    pub fn take_tone_events(&mut self) -> Vec<ToneEvent> {
+        return self.sound_timer.take_events();
+    }
+
+    // Sound timer ticks since the cpu was created, the time base of the tone events
+    pub fn timer_ticks(&self) -> u64 {
+        return self.sound_timer.tick();
+    }
+
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        return self.sound_timer.ticks_to_duration(ticks);
+    }
+
+    pub fn display(&self) -> &Framebuffer {
+        return &self.display;
+    }
+
+    pub fn display_mut(&mut self) -> &mut Framebuffer {
+        return &mut self.display;
+    }
+
+    // The last complete frame, or the display being drawn in immediate mode
+    pub fn frame(&self) -> &Framebuffer {
+        return match self.present_mode {
+            PresentMode::Frame | PresentMode::NonErasing => &self.frame,
+            PresentMode::Immediate => &self.display,
+        };
+    }
+
+    pub fn frame_mut(&mut self) -> &mut Framebuffer {
+        return match self.present_mode {
+            PresentMode::Frame | PresentMode::NonErasing => &mut self.frame,
+            PresentMode::Immediate => &mut self.display,
+        };
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        return self.frame_count;
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        return self.present_mode;
+    }
+
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        self.present_mode = mode;
+        self.frame.copy_from(&self.display);
+    }
+
+    // While halted no frame ends, single steps are made visible by syncing the frame with the display
+    pub fn sync_frame(&mut self) {
+        if self.present_mode != PresentMode::Immediate {
+            self.frame.copy_from(&self.display);
+            self.frame_ready |= self.frame.dirty_rect().is_some();
+        }
+    }
+
+    // True once per frame ready to be presented
+    // Schedules without a frame tick at the start of the first frame open it explicitly
+    pub fn open_frame(&mut self) {
+        self.frame_started = true;
+    }
+
+    pub fn take_frame_ready(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        return ready;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        return self.quirks;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn layout(&self) -> MemoryLayout {
+        return self.layout;
+    }
+
+    // Makes Cxnn reproducible
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        self.keys[key as usize % KEY_COUNT] = down;
+    }
+
+    pub fn is_key_down(&self, key: u8) -> bool {
+        return self.keys[key as usize % KEY_COUNT];
+    }
+
+    pub fn read_opcode(&self, address: usize) -> Option<u16> {
+        let high = self.read_memory(address)?;
+        let low = self.read_memory(address + 1)?;
+        return Some((high as u16) << 8 | low as u16);
+    }
+
+    pub fn memory_size(&self) -> usize {
+        return self.memory.len();
+    }
+
+    pub fn read_memory(&self, address: usize) -> Option<u8> {
+        return self.memory.peek(address);
+    }
+
+    pub fn write_memory(&mut self, address: usize, value: u8) -> bool {
+        return self.memory.poke(address, value);
+    }
+
+    pub fn attach_memory_observer(&mut self, observer: Box<dyn MemoryObserver + Send>) {
+        self.memory.attach_observer(observer);
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        return self.breakpoints.iter();
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16) {
+        let value = self.read_memory(address as usize).unwrap_or(0);
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) {
+        self.watchpoints.remove(&address);
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &u16> {
+        return self.watchpoints.keys();
+    }
+
+    pub fn attach_tracer(&mut self, tracer: Box<dyn Tracer + Send>) {
+        self.tracers.push(tracer);
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.halt_reason.is_some();
+    }
+
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        return self.halt_reason;
+    }
+
+    pub fn halt(&mut self) {
+        self.halt_reason = Some(HaltReason::Request);
+    }
+
+    // Leaving a breakpoint must execute the instruction it stopped on
+    pub fn resume(&mut self) {
+        self.halt_reason = None;
+        self.skip_breakpoint = true;
+    }
+
+    // A watched write halts with HaltReason::Watchpoint, like while running
+    pub fn step(&mut self) {
+        self.execute_instruction();
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints();
+        }
+    }
+
     fn initialize_memory(&mut self) {
-        self.memory = vec![0; MEMORY_SIZE];
-        let mut index = 0;
-        for font in &FONTS {
-            self.memory[index] = *font;
+        self.memory.clear();
+        let mut index = self.layout.font_address;
+        for font in &self.font {
+            self.memory.write(index, *font, Access::Load);
             index += 1;
         }
-        index = PROGRAM_START;
+        index = self.layout.program_start;
         for data in &self.rom {
-            self.memory[index] = *data;
+            self.memory.write(index, *data, Access::Load);
             index += 1;
         }
     }
 
     fn clear_display(&mut self) {
-        // TODO: Maybe not the most efficient
-        self.display = vec![vec![0; Y_SIZE]; X_SIZE];
+        self.display.clear();
+        self.display_changed();
+    }
+
+    fn display_changed(&mut self) {
+        if self.present_mode == PresentMode::Immediate {
+            self.frame_ready = true;
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_count += 1;
+        match self.present_mode {
+            PresentMode::Frame => {
+                self.frame.copy_from(&self.display);
+                self.frame_ready = true;
+            }
+            PresentMode::NonErasing => self.frame_ready = true,
+            PresentMode::Immediate => {}
+        }
     }
 
     fn pop(&mut self) -> u16 {
@@ -183,9 +553,18 @@ impl Cpu {
         let y = self.y() as usize;
         match n {
             0x0 => self.v[x] = self.v[y],
-            0x1 => self.v[x] |= self.v[y],
-            0x2 => self.v[x] &= self.v[y],
-            0x3 => self.v[x] ^= self.v[y],
+            0x1 => {
+                self.v[x] |= self.v[y];
+                self.reset_vf_after_logic();
+            }
+            0x2 => {
+                self.v[x] &= self.v[y];
+                self.reset_vf_after_logic();
+            }
+            0x3 => {
+                self.v[x] ^= self.v[y];
+                self.reset_vf_after_logic();
+            }
             0x4 => {
                 self.v[0xF] = if self.v[x] as u16 + self.v[y] as u16 > 0xFF {
                     1
@@ -199,6 +578,7 @@ impl Cpu {
                 self.v[x] -= self.v[y];
             }
             0x6 => {
+                self.load_vy_before_shift(x, y);
                 self.v[0xF] = self.v[x] & 0x1;
                 self.v[x] >>= 1;
             }
@@ -207,6 +587,7 @@ impl Cpu {
                 self.v[x] = self.v[y] - self.v[x];
             }
             0xE => {
+                self.load_vy_before_shift(x, y);
                 self.v[0xF] = self.v[x] >> 7;
                 self.v[x] <<= 1;
             }
@@ -214,6 +595,18 @@ impl Cpu {
         }
     }
 
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn load_vy_before_shift(&mut self, x: usize, y: usize) {
+        if self.quirks.shift_uses_vy {
+            self.v[x] = self.v[y];
+        }
+    }
+
     fn instructions_9(&mut self) {
         let x = self.x() as usize;
         let y = self.y() as usize;
@@ -227,13 +620,14 @@ impl Cpu {
     }
 
     fn instructions_b(&mut self) {
-        self.program_counter = self.nnn() as usize + self.v[0] as usize;
+        let register = if self.quirks.jump_uses_vx { self.x() as usize } else { 0 };
+        self.program_counter = self.nnn() as usize + self.v[register] as usize;
     }
 
     fn instructions_c(&mut self) {
         let x = self.x() as usize;
         let nn = self.nn();
-        let value = rand::random::<u8>();
+        let value = self.rng.gen::<u8>();
         self.v[x] = value & nn;
     }
 
@@ -244,29 +638,22 @@ impl Cpu {
         let vy = self.v[y];
         let n = self.n();
 
-        self.v[0xF] = 0;
+        let wrap = self.quirks.wrap_sprites;
+        let mut collision = false;
 
         for row in 0..n {
-            let pixels = self.memory[(self.i + row as u16) as usize];
-            for col in 0..8 {
-                if bit_value(pixels, 7 - col) {
-                    let dx = (vx + col) as usize;
-                    let dy = (vy + row) as usize;
-
-                    if dx >= X_SIZE || dy >= Y_SIZE {
-                        continue;
-                    }
-
-                    if self.display[dx][dy] == 1 {
-                        self.v[0xF] = 1;
-                    }
-
-                    self.display[dx][dy] ^= 1;
-                }
-            }
+            let pixels = self.memory.read((self.i + row as u16) as usize, Access::Sprite);
+            let bits = (pixels as u64) << SPRITE_SHIFT;
+            collision |= self
+                .display
+                .xor_row(0, vx as usize, vy as usize + row as usize, bits, wrap);
         }
 
-        self.driver.borrow_mut().video_fill_buffer(&self.display);
+        self.v[0xF] = collision as u8;
+        if self.present_mode == PresentMode::NonErasing && !collision {
+            self.frame.copy_from(&self.display);
+        }
+        self.display_changed();
     }
 
     fn instructions_e(&mut self) {
@@ -276,12 +663,12 @@ impl Cpu {
 
         match nn {
             0x9E => {
-                if self.driver.borrow_mut().input_is_key_down(vx) {
+                if self.is_key_down(vx) {
                     self.program_counter += 2;
                 }
             }
             0xA1 => {
-                if self.driver.borrow_mut().input_is_key_up(vx) {
+                if !self.is_key_down(vx) {
                     self.program_counter += 2;
                 }
             }
@@ -294,43 +681,94 @@ impl Cpu {
         let nn = self.nn();
 
         match nn {
-            0x07 => self.v[x] = self.delay_timer.borrow().value,
+            0x02 if x == 0 => {
+                let mut buffer = [0; PATTERN_SIZE];
+                for (offset, byte) in buffer.iter_mut().enumerate() {
+                    *byte = self.memory.read(self.i as usize + offset, Access::Data);
+                }
+                self.sound_timer.set_pattern(buffer);
+            }
+            0x07 => self.v[x] = self.delay_timer.value,
             0x0A => {
-                let mut key = 0u8;
-                if self.driver.borrow_mut().input_is_any_key_down(&mut key) {
-                    self.v[x] = key;
-                } else {
-                    self.program_counter += 2;
+                let key = (0..KEY_COUNT as u8).find(|key| self.is_key_down(*key));
+                match key {
+                    Some(key) => self.v[x] = key,
+                    None => self.program_counter += 2,
                 }
             }
-            0x15 => self.delay_timer.borrow_mut().value = self.v[x],
-            0x18 => self.sound_timer.borrow_mut().cpu_timer.value = self.v[x],
+            0x15 => self.delay_timer.value = self.v[x],
+            0x18 => self.sound_timer.set(self.v[x]),
             0x1E => self.i += self.v[x] as u16,
-            0x29 => self.i = self.v[x] as u16 * 5,
+            0x29 => self.i = self.layout.font_address as u16 + self.v[x] as u16 * GLYPH_SIZE,
             0x33 => {
-                self.memory[(self.i + 0) as usize] = ((self.v[x] as u32 / 100) % 10) as u8;
-                self.memory[(self.i + 1) as usize] = ((self.v[x] as u32 / 10) % 10) as u8;
-                self.memory[(self.i + 2) as usize] = (self.v[x] as u32 % 10) as u8;
+                let i = self.i as usize;
+                let vx = self.v[x] as u32;
+                self.memory.write(i, ((vx / 100) % 10) as u8, Access::Data);
+                self.memory.write(i + 1, ((vx / 10) % 10) as u8, Access::Data);
+                self.memory.write(i + 2, (vx % 10) as u8, Access::Data);
             }
+            0x3A => self.sound_timer.set_pitch(self.v[x]),
             0x55 => {
                 for i in 0..=x {
-                    self.memory[self.i as usize + i] = self.v[i];
+                    self.memory.write(self.i as usize + i, self.v[i], Access::Data);
                 }
+                self.increment_i_after_load_store(x);
             }
             0x65 => {
                 for i in 0..=x {
-                    self.v[i] = self.memory[self.i as usize + i];
+                    self.v[i] = self.memory.read(self.i as usize + i, Access::Data);
                 }
+                self.increment_i_after_load_store(x);
             }
             _ => {}
         }
     }
 }
 
-impl Processor for Cpu {
-    fn execute(&mut self) -> u8 {
-        self.current_opcode = (self.memory[self.program_counter] as u16) << 8
-            | self.memory[self.program_counter + 1] as u16;
+impl Cpu {
+    fn increment_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.i += x as u16 + 1;
+        }
+    }
+
+    fn check_watchpoints(&mut self) {
+        for (address, value) in self.watchpoints.iter_mut() {
+            let new = self.memory.peek(*address as usize).unwrap_or(0);
+            if new != *value {
+                self.halt_reason = Some(HaltReason::Watchpoint {
+                    address: *address,
+                    old: *value,
+                    new: new,
+                });
+                *value = new;
+            }
+        }
+    }
+
+    fn trace(&mut self) {
+        let mut v = [0; V_SIZE];
+        v.copy_from_slice(&self.v);
+        let entry = TraceEntry {
+            program_counter: self.program_counter as u16,
+            opcode: self.current_opcode,
+            v: v,
+            i: self.i,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer.value,
+            sound_timer: self.sound_timer.value(),
+        };
+        for tracer in self.tracers.iter_mut() {
+            tracer.trace(&entry);
+        }
+    }
+
+    fn execute_instruction(&mut self) {
+        self.current_opcode = (self.memory.read(self.program_counter, Access::Fetch) as u16) << 8
+            | self.memory.read(self.program_counter + 1, Access::Fetch) as u16;
+        if !self.tracers.is_empty() {
+            self.trace();
+        }
         self.program_counter += 2;
         let instructions_index = self.current_opcode >> 12;
         match instructions_index {
@@ -352,21 +790,122 @@ impl Processor for Cpu {
             0xf => self.instructions_f(),
             _ => {}
         }
+    }
+}
+
+impl Processor for Cpu {
+    fn execute(&mut self) -> u8 {
+        if self.halt_reason.is_some() {
+            return 1;
+        }
+
+        let address = self.program_counter as u16;
+        if !self.skip_breakpoint && self.breakpoints.contains(&address) {
+            self.halt_reason = Some(HaltReason::Breakpoint(address));
+            for tracer in self.tracers.iter_mut() {
+                tracer.breakpoint(address);
+            }
+            return 1;
+        }
+
+        self.skip_breakpoint = false;
+        self.execute_instruction();
+
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints();
+        }
         return 1;
     }
 
     fn reset(&mut self) {
         self.current_opcode = 0;
         self.i = 0;
-        self.program_counter = PROGRAM_START;
+        self.program_counter = self.layout.program_start;
         self.stack_pointer = 0;
         self.v = vec![0; V_SIZE];
         self.stack = vec![0; STACK_SIZE];
+        self.halt_reason = None;
+        self.skip_breakpoint = false;
         self.clear_display();
+        self.frame.copy_from(&self.display);
+        self.frame_count = 0;
+        self.frame_ready = true;
         self.initialize_memory();
+        for (address, value) in self.watchpoints.iter_mut() {
+            *value = self.memory.peek(*address as usize).unwrap_or(0);
+        }
     }
 }
 
-fn bit_value(value: u8, position: u8) -> bool {
-    return (value & (1 << position)) != 0;
+// Tracers and memory observers stay attached to the original, a clone only copies the machine state
+impl Clone for Cpu {
+    fn clone(&self) -> Self {
+        return Self {
+            i: self.i,
+            v: self.v.clone(),
+            program_counter: self.program_counter,
+            stack: self.stack.clone(),
+            stack_pointer: self.stack_pointer,
+            display: self.display.clone(),
+            frame: self.frame.clone(),
+            present_mode: self.present_mode,
+            frame_count: self.frame_count,
+            frame_ready: self.frame_ready,
+            frame_started: self.frame_started,
+            memory: self.memory.clone(),
+            layout: self.layout,
+            font: self.font.clone(),
+            quirks: self.quirks,
+            delay_timer: self.delay_timer.clone(),
+            sound_timer: self.sound_timer.clone(),
+            keys: self.keys,
+            rng: self.rng.clone(),
+            current_opcode: self.current_opcode,
+            rom: self.rom.clone(),
+            breakpoints: self.breakpoints.clone(),
+            watchpoints: self.watchpoints.clone(),
+            halt_reason: self.halt_reason,
+            skip_breakpoint: self.skip_breakpoint,
+            tracers: Vec::new(),
+        };
+    }
 }
+
+impl Processors for Cpu {
+    fn execute_thread(&mut self, thread: usize) -> u8 {
+        return match thread {
+            DELAY_TIMER_THREAD => self.delay_timer.execute(),
+            SOUND_TIMER_THREAD => self.sound_timer.execute(),
+            // The frame thread ticks at the start of each frame, so the first tick only opens the first frame
+            FRAME_THREAD => {
+                if self.frame_started {
+                    self.end_frame();
+                }
+                self.frame_started = true;
+                1
+            }
+            _ => Processor::execute(self),
+        };
+    }
+
+    fn reset_thread(&mut self, thread: usize) {
+        match thread {
+            DELAY_TIMER_THREAD => self.delay_timer.reset(),
+            SOUND_TIMER_THREAD => self.sound_timer.reset(),
+            FRAME_THREAD => self.frame_started = false,
+            _ => Processor::reset(self),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn new_rng() -> SmallRng {
+    return SmallRng::from_entropy();
+}
+
+// Without std there is no entropy source, the sequence is fixed until seeded
+#[cfg(not(feature = "std"))]
+fn new_rng() -> SmallRng {
+    return SmallRng::seed_from_u64(DEFAULT_SEED);
+}
+
@@ -0,0 +1,163 @@
+use alloc::format;
+use alloc::string::String;
+
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let nn = (opcode & 0x00FF) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+
+    return match opcode >> 12 {
+        0x0 => match nnn {
+            0x0E0 => String::from("CLS"),
+            0x0EE => String::from("RET"),
+            _ => format!("SYS 0x{:03X}", nnn),
+        },
+        0x1 => format!("JP 0x{:03X}", nnn),
+        0x2 => format!("CALL 0x{:03X}", nnn),
+        0x3 => format!("SE V{:X}, 0x{:02X}", x, nn),
+        0x4 => format!("SNE V{:X}, 0x{:02X}", x, nn),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, 0x{:02X}", x, nn),
+        0x7 => format!("ADD V{:X}, 0x{:02X}", x, nn),
+        0x8 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            // Vy is kept, the shift_uses_vy quirk shifts it into Vx
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => data_word(opcode),
+        },
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, 0x{:03X}", nnn),
+        0xB => format!("JP V0, 0x{:03X}", nnn),
+        0xC => format!("RND V{:X}, 0x{:02X}", x, nn),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match nn {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data_word(opcode),
+        },
+        0xF => match nn {
+            0x02 if x == 0 => String::from("AUDIO"),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => data_word(opcode),
+        },
+        _ => data_word(opcode),
+    };
+}
+
+pub fn opcode_class(opcode: u16) -> &'static str {
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+
+    return match opcode >> 12 {
+        0x0 => match opcode & 0x0FFF {
+            0x0E0 => "00E0",
+            0x0EE => "00EE",
+            _ => "0nnn",
+        },
+        0x1 => "1nnn",
+        0x2 => "2nnn",
+        0x3 => "3xnn",
+        0x4 => "4xnn",
+        0x5 if n == 0 => "5xy0",
+        0x6 => "6xnn",
+        0x7 => "7xnn",
+        0x8 => match n {
+            0x0 => "8xy0",
+            0x1 => "8xy1",
+            0x2 => "8xy2",
+            0x3 => "8xy3",
+            0x4 => "8xy4",
+            0x5 => "8xy5",
+            0x6 => "8xy6",
+            0x7 => "8xy7",
+            0xE => "8xyE",
+            _ => "????",
+        },
+        0x9 if n == 0 => "9xy0",
+        0xA => "Annn",
+        0xB => "Bnnn",
+        0xC => "Cxnn",
+        0xD => "Dxyn",
+        0xE => match nn {
+            0x9E => "Ex9E",
+            0xA1 => "ExA1",
+            _ => "????",
+        },
+        0xF => match nn {
+            0x02 if opcode & 0x0F00 == 0 => "F002",
+            0x07 => "Fx07",
+            0x0A => "Fx0A",
+            0x15 => "Fx15",
+            0x18 => "Fx18",
+            0x1E => "Fx1E",
+            0x29 => "Fx29",
+            0x33 => "Fx33",
+            0x3A => "Fx3A",
+            0x55 => "Fx55",
+            0x65 => "Fx65",
+            _ => "????",
+        },
+        _ => "????",
+    };
+}
+
+fn data_word(opcode: u16) -> String {
+    return format!("DW 0x{:04X}", opcode);
+}
+
+#[cfg(test)]
+mod disasm_tests {
+    use super::disassemble;
+    use super::opcode_class;
+    use test_case::test_case;
+
+    #[test_case(0x00E0, "CLS")]
+    #[test_case(0x00EE, "RET")]
+    #[test_case(0x1228, "JP 0x228")]
+    #[test_case(0x2ABC, "CALL 0xABC")]
+    #[test_case(0x3A05, "SE VA, 0x05")]
+    #[test_case(0x5120, "SE V1, V2")]
+    #[test_case(0x8126, "SHR V1, V2")]
+    #[test_case(0x830E, "SHL V3, V0")]
+    #[test_case(0xB300, "JP V0, 0x300")]
+    #[test_case(0xD015, "DRW V0, V1, 5")]
+    #[test_case(0xE4A1, "SKNP V4")]
+    #[test_case(0xF265, "LD V2, [I]")]
+    #[test_case(0xF002, "AUDIO")]
+    #[test_case(0xF53A, "PITCH V5")]
+    #[test_case(0x5121, "DW 0x5121")]
+    #[test_case(0xF0FF, "DW 0xF0FF")]
+    fn given_opcode_when_disassemble_should_format_mnemonic(opcode: u16, expected: &str) {
+        assert_eq!(disassemble(opcode), expected);
+    }
+
+    #[test_case(0x00E0, "00E0")]
+    #[test_case(0x2ABC, "2nnn")]
+    #[test_case(0x812E, "8xyE")]
+    #[test_case(0xD015, "Dxyn")]
+    #[test_case(0xF133, "Fx33")]
+    #[test_case(0xF002, "F002")]
+    #[test_case(0xF102, "????")]
+    #[test_case(0xF1FF, "????")]
+    fn given_opcode_when_opcode_class_should_return_pattern(opcode: u16, expected: &str) {
+        assert_eq!(opcode_class(opcode), expected);
+    }
+}
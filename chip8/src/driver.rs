@@ -15,10 +15,206 @@ pub const KEY_D: u8 = 0xD;
 pub const KEY_E: u8 = 0xE;
 pub const KEY_F: u8 = 0xF;
 
+use super::framebuffer::Framebuffer;
+use super::timers::AudioPattern;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::time::Duration;
+
+// The length given when the tone stops is in emulated time, so tones shorter than a host frame are not lost
+pub trait AudioSink {
+    fn tone_on(&mut self, frequency: u32);
+    fn tone_off(&mut self, length: Duration);
+
+    // XO-CHIP pattern played instead of the beeper while set, sinks that only beep can ignore it
+    fn set_pattern(&mut self, _pattern: Option<AudioPattern>) {}
+
+    // Emulated time elapsed since the previous call, sinks playing in real time can ignore it
+    fn advance(&mut self, _elapsed: Duration) {}
+}
+
+pub trait VideoSink {
+    fn fill_buffer(&mut self, framebuffer: &Framebuffer);
+}
+
+pub trait Keypad {
+    fn is_key_down(&mut self, key: u8) -> bool;
+    fn is_key_up(&mut self, key: u8) -> bool;
+    fn any_key_down(&mut self) -> Option<u8>;
+}
+
+// All-in-one driver, every implementation is also an AudioSink, a VideoSink and a Keypad
 pub trait Driver {
-    fn sound_do_beep(&mut self, frequency: u32, duration: u32);
-    fn video_fill_buffer(&mut self, display: &Vec<Vec<usize>>);
+    fn sound_tone_on(&mut self, frequency: u32);
+    fn sound_tone_off(&mut self, length: Duration);
+    fn video_fill_buffer(&mut self, framebuffer: &Framebuffer);
     fn input_is_key_down(&mut self, key: u8) -> bool;
     fn input_is_key_up(&mut self, key: u8) -> bool;
     fn input_is_any_key_down(&mut self, key: &mut u8) -> bool;
 }
+
+impl<T: Driver + ?Sized> AudioSink for T {
+    fn tone_on(&mut self, frequency: u32) {
+        self.sound_tone_on(frequency);
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.sound_tone_off(length);
+    }
+}
+
+impl<T: Driver + ?Sized> VideoSink for T {
+    fn fill_buffer(&mut self, framebuffer: &Framebuffer) {
+        self.video_fill_buffer(framebuffer);
+    }
+}
+
+impl<T: Driver + ?Sized> Keypad for T {
+    fn is_key_down(&mut self, key: u8) -> bool {
+        return self.input_is_key_down(key);
+    }
+
+    fn is_key_up(&mut self, key: u8) -> bool {
+        return self.input_is_key_up(key);
+    }
+
+    fn any_key_down(&mut self) -> Option<u8> {
+        let mut key = 0u8;
+        if self.input_is_any_key_down(&mut key) {
+            return Some(key);
+        }
+        return None;
+    }
+}
+
+// A shared driver can be handed to the core while the frontend keeps drawing and polling events with it
+impl<T: AudioSink + ?Sized> AudioSink for Rc<RefCell<T>> {
+    fn tone_on(&mut self, frequency: u32) {
+        self.borrow_mut().tone_on(frequency);
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.borrow_mut().tone_off(length);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.borrow_mut().set_pattern(pattern);
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        self.borrow_mut().advance(elapsed);
+    }
+}
+
+// Plays the same sound on several sinks, e.g. the speakers and a recorder
+impl AudioSink for Vec<Box<dyn AudioSink>> {
+    fn tone_on(&mut self, frequency: u32) {
+        self.iter_mut().for_each(|sink| sink.tone_on(frequency));
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.iter_mut().for_each(|sink| sink.tone_off(length));
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.iter_mut().for_each(|sink| sink.set_pattern(pattern));
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        self.iter_mut().for_each(|sink| sink.advance(elapsed));
+    }
+}
+
+impl<T: VideoSink + ?Sized> VideoSink for Rc<RefCell<T>> {
+    fn fill_buffer(&mut self, framebuffer: &Framebuffer) {
+        self.borrow_mut().fill_buffer(framebuffer);
+    }
+}
+
+impl<T: Keypad + ?Sized> Keypad for Rc<RefCell<T>> {
+    fn is_key_down(&mut self, key: u8) -> bool {
+        return self.borrow_mut().is_key_down(key);
+    }
+
+    fn is_key_up(&mut self, key: u8) -> bool {
+        return self.borrow_mut().is_key_up(key);
+    }
+
+    fn any_key_down(&mut self) -> Option<u8> {
+        return self.borrow_mut().any_key_down();
+    }
+}
+
+#[cfg(test)]
+mod driver_tests {
+    use super::AudioSink;
+    use super::Driver;
+    use super::Keypad;
+    use super::VideoSink;
+    use crate::framebuffer::Framebuffer;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+    use core::time::Duration;
+
+    struct FakeDriver {
+        tones: Vec<(u32, Option<Duration>)>,
+        frames: usize,
+        key: Option<u8>,
+    }
+
+    impl Driver for FakeDriver {
+        fn sound_tone_on(&mut self, frequency: u32) {
+            self.tones.push((frequency, None));
+        }
+
+        fn sound_tone_off(&mut self, length: Duration) {
+            self.tones.last_mut().unwrap().1 = Some(length);
+        }
+
+        fn video_fill_buffer(&mut self, _framebuffer: &Framebuffer) {
+            self.frames += 1;
+        }
+
+        fn input_is_key_down(&mut self, key: u8) -> bool {
+            return self.key == Some(key);
+        }
+
+        fn input_is_key_up(&mut self, key: u8) -> bool {
+            return self.key != Some(key);
+        }
+
+        fn input_is_any_key_down(&mut self, key: &mut u8) -> bool {
+            if let Some(value) = self.key {
+                *key = value;
+                return true;
+            }
+            return false;
+        }
+    }
+
+    #[test]
+    fn given_driver_when_used_as_split_traits_should_forward_calls() {
+        let driver = Rc::new(RefCell::new(FakeDriver {
+            tones: Vec::new(),
+            frames: 0,
+            key: Some(0xA),
+        }));
+        let audio: Rc<RefCell<dyn AudioSink>> = driver.clone();
+        let video: Rc<RefCell<dyn VideoSink>> = driver.clone();
+        let keypad: Rc<RefCell<dyn Keypad>> = driver.clone();
+
+        audio.borrow_mut().tone_on(800);
+        audio.borrow_mut().tone_off(Duration::from_millis(100));
+        video.borrow_mut().fill_buffer(&Framebuffer::new(64, 32, 1));
+
+        assert_eq!(driver.borrow().tones, vec![(800, Some(Duration::from_millis(100)))]);
+        assert_eq!(driver.borrow().frames, 1);
+        assert!(keypad.borrow_mut().is_key_down(0xA));
+        assert!(keypad.borrow_mut().is_key_up(0xB));
+        assert_eq!(keypad.borrow_mut().any_key_down(), Some(0xA));
+    }
+}
@@ -0,0 +1,320 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+const WORD_BITS: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// One bit per pixel and per plane, rows are packed in u64 words with the leftmost pixel in the high bit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: usize,
+    words_per_row: usize,
+    words: Vec<u64>,
+    dirty_rows: Vec<bool>,
+    dirty_rect: Option<Rect>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, planes: usize) -> Self {
+        let words_per_row = width.div_ceil(WORD_BITS);
+        return Self {
+            width: width,
+            height: height,
+            planes: planes,
+            words_per_row: words_per_row,
+            words: vec![0; words_per_row * height * planes],
+            dirty_rows: vec![true; height],
+            dirty_rect: Some(Rect {
+                x: 0,
+                y: 0,
+                width: width,
+                height: height,
+            }),
+        };
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    pub fn planes(&self) -> usize {
+        return self.planes;
+    }
+
+    pub fn get(&self, plane: usize, x: usize, y: usize) -> bool {
+        let word = self.words[self.row_index(plane, y) + x / WORD_BITS];
+        return word & pixel_mask(x) != 0;
+    }
+
+    pub fn set(&mut self, plane: usize, x: usize, y: usize, on: bool) {
+        let index = self.row_index(plane, y) + x / WORD_BITS;
+        if on {
+            self.words[index] |= pixel_mask(x);
+        } else {
+            self.words[index] &= !pixel_mask(x);
+        }
+        self.mark_dirty(x, y, 1);
+    }
+
+    // Bit n is set when the pixel is on in plane n
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let mut value = 0;
+        for plane in 0..self.planes {
+            if self.get(plane, x, y) {
+                value |= 1 << plane;
+            }
+        }
+        return value;
+    }
+
+    pub fn row_words(&self, plane: usize, y: usize) -> &[u64] {
+        let index = self.row_index(plane, y);
+        return &self.words[index..index + self.words_per_row];
+    }
+
+    pub fn clear(&mut self) {
+        if self.words.iter().all(|word| *word == 0) {
+            return;
+        }
+        self.words.iter_mut().for_each(|word| *word = 0);
+        self.mark_dirty(0, 0, self.width);
+        self.mark_dirty(0, self.height - 1, self.width);
+        self.dirty_rows.iter_mut().for_each(|dirty| *dirty = true);
+    }
+
+    // Sprite bits are left aligned, returns true when a lit pixel was turned off
+    pub fn xor_row(&mut self, plane: usize, x: usize, y: usize, bits: u64, wrap: bool) -> bool {
+        if bits == 0 {
+            return false;
+        }
+
+        let length = WORD_BITS - bits.trailing_zeros() as usize;
+        if !wrap {
+            return self.xor_span(plane, x, y, bits, length);
+        }
+
+        let x = x % self.width;
+        let y = y % self.height;
+        let mut collision = self.xor_span(plane, x, y, bits, length);
+        let visible = self.width - x;
+        if visible < length {
+            collision |= self.xor_span(plane, 0, y, bits << visible, length - visible);
+        }
+        return collision;
+    }
+
+    // Copies the rows that differ and marks them dirty, so that consecutive frames only report real changes
+    pub fn copy_from(&mut self, other: &Framebuffer) {
+        if self.width != other.width || self.height != other.height || self.planes != other.planes {
+            *self = other.clone();
+            return;
+        }
+
+        for plane in 0..self.planes {
+            for y in 0..self.height {
+                let index = self.row_index(plane, y);
+                let row = index..index + self.words_per_row;
+                if self.words[row.clone()] != other.words[row.clone()] {
+                    self.words[row.clone()].copy_from_slice(&other.words[row]);
+                    self.mark_dirty(0, y, self.width);
+                }
+            }
+        }
+    }
+
+    pub fn is_row_dirty(&self, y: usize) -> bool {
+        return self.dirty_rows[y];
+    }
+
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        return (0..self.height).filter(|y| self.dirty_rows[*y]);
+    }
+
+    pub fn dirty_rect(&self) -> Option<Rect> {
+        return self.dirty_rect;
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rows.iter_mut().for_each(|dirty| *dirty = false);
+        self.dirty_rect = None;
+    }
+
+    fn row_index(&self, plane: usize, y: usize) -> usize {
+        return (plane * self.height + y) * self.words_per_row;
+    }
+
+    // Pixels past the end of the row are dropped
+    fn xor_span(&mut self, plane: usize, x: usize, y: usize, bits: u64, length: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+
+        let row = self.row_index(plane, y);
+        let word = x / WORD_BITS;
+        let shift = x % WORD_BITS;
+        let mut collision = self.xor_word(row, word, bits >> shift);
+        if shift > 0 && word + 1 < self.words_per_row {
+            collision |= self.xor_word(row, word + 1, bits << (WORD_BITS - shift));
+        }
+        self.mark_dirty(x, y, length.min(self.width - x));
+        return collision;
+    }
+
+    fn xor_word(&mut self, row: usize, word: usize, value: u64) -> bool {
+        let value = value & self.word_mask(word);
+        let index = row + word;
+        let collision = self.words[index] & value != 0;
+        self.words[index] ^= value;
+        return collision;
+    }
+
+    // Masks the pixels past the width in the last word of a row
+    fn word_mask(&self, word: usize) -> u64 {
+        let remaining = self.width - word * WORD_BITS;
+        if remaining >= WORD_BITS {
+            return u64::MAX;
+        }
+        return !(u64::MAX >> remaining);
+    }
+
+    fn mark_dirty(&mut self, x: usize, y: usize, width: usize) {
+        self.dirty_rows[y] = true;
+        self.dirty_rect = Some(match self.dirty_rect {
+            None => Rect {
+                x: x,
+                y: y,
+                width: width,
+                height: 1,
+            },
+            Some(rect) => {
+                let left = rect.x.min(x);
+                let top = rect.y.min(y);
+                let right = (rect.x + rect.width).max(x + width);
+                let bottom = (rect.y + rect.height).max(y + 1);
+                Rect {
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    height: bottom - top,
+                }
+            }
+        });
+    }
+}
+
+fn pixel_mask(x: usize) -> u64 {
+    return 1 << (WORD_BITS - 1 - x % WORD_BITS);
+}
+
+#[cfg(test)]
+mod framebuffer_tests {
+    use super::Framebuffer;
+    use super::Rect;
+    use alloc::vec::Vec;
+
+    const SPRITE: u64 = 0xF0 << 56;
+
+    #[test]
+    fn given_sprite_row_when_xor_twice_should_report_collision_and_erase() {
+        let mut framebuffer = Framebuffer::new(64, 32, 1);
+
+        let first = framebuffer.xor_row(0, 10, 3, SPRITE, false);
+        let second = framebuffer.xor_row(0, 10, 3, SPRITE, false);
+
+        assert!(!first);
+        assert!(second);
+        assert_eq!(framebuffer.row_words(0, 3), &[0]);
+    }
+
+    #[test]
+    fn given_row_across_words_when_xor_should_split_bits() {
+        let mut framebuffer = Framebuffer::new(128, 64, 1);
+
+        framebuffer.xor_row(0, 62, 0, SPRITE, false);
+
+        assert_eq!(framebuffer.row_words(0, 0), &[0x3, 0xC000_0000_0000_0000]);
+        assert!(framebuffer.get(0, 65, 0));
+        assert!(!framebuffer.get(0, 66, 0));
+    }
+
+    #[test]
+    fn given_sprite_on_right_edge_when_xor_should_clip_or_wrap() {
+        let mut clipped = Framebuffer::new(64, 32, 1);
+        let mut wrapped = Framebuffer::new(64, 32, 1);
+
+        clipped.xor_row(0, 62, 0, SPRITE, false);
+        wrapped.xor_row(0, 62, 33, SPRITE, true);
+
+        assert_eq!(clipped.row_words(0, 0), &[0x3]);
+        assert_eq!(wrapped.row_words(0, 1), &[0xC000_0000_0000_0003]);
+    }
+
+    #[test]
+    fn given_planes_when_pixel_should_combine_bits() {
+        let mut framebuffer = Framebuffer::new(64, 32, 2);
+
+        framebuffer.set(0, 5, 5, true);
+        framebuffer.set(1, 5, 5, true);
+        framebuffer.set(1, 6, 5, true);
+
+        assert_eq!(framebuffer.pixel(5, 5), 0b11);
+        assert_eq!(framebuffer.pixel(6, 5), 0b10);
+        assert_eq!(framebuffer.pixel(7, 5), 0);
+    }
+
+    #[test]
+    fn given_changes_when_clear_dirty_should_track_only_new_rows() {
+        let mut framebuffer = Framebuffer::new(64, 32, 1);
+        framebuffer.clear_dirty();
+
+        framebuffer.xor_row(0, 8, 4, SPRITE, false);
+        framebuffer.xor_row(0, 20, 6, 0x80 << 56, false);
+
+        assert_eq!(framebuffer.dirty_rows().collect::<Vec<usize>>(), [4, 6]);
+        assert_eq!(
+            framebuffer.dirty_rect(),
+            Some(Rect {
+                x: 8,
+                y: 4,
+                width: 13,
+                height: 3
+            })
+        );
+    }
+
+    #[test]
+    fn given_changed_source_when_copy_from_should_mark_changed_rows_only() {
+        let mut source = Framebuffer::new(64, 32, 1);
+        let mut frame = Framebuffer::new(64, 32, 1);
+        source.xor_row(0, 0, 2, SPRITE, false);
+        frame.clear_dirty();
+
+        frame.copy_from(&source);
+
+        assert_eq!(frame.row_words(0, 2), source.row_words(0, 2));
+        assert_eq!(frame.dirty_rows().collect::<Vec<usize>>(), [2]);
+    }
+
+    #[test]
+    fn given_empty_framebuffer_when_clear_should_stay_clean() {
+        let mut framebuffer = Framebuffer::new(64, 32, 1);
+        framebuffer.clear_dirty();
+
+        framebuffer.clear();
+
+        assert_eq!(framebuffer.dirty_rect(), None);
+    }
+}
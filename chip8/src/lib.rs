@@ -1,5 +1,24 @@
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod asm;
+pub mod audio;
+pub mod builder;
+pub mod bus;
+pub mod cheats;
 pub mod constants;
 pub mod cpu;
+pub mod disasm;
 pub mod driver;
+pub mod framebuffer;
+pub mod machine;
+pub mod profiler;
 pub mod threading;
 pub mod timers;
+pub mod trace;
+
+pub use builder::Chip8Builder;
+pub use machine::Machine;
@@ -0,0 +1,460 @@
+use super::constants::KEY_COUNT;
+use super::cpu::Cpu;
+use super::cpu::CPU_THREAD;
+use super::cpu::DELAY_TIMER_THREAD;
+use super::cpu::FRAME_THREAD;
+use super::cpu::SOUND_TIMER_THREAD;
+use super::cpu::THREAD_COUNT;
+use super::driver::AudioSink;
+use super::driver::Keypad;
+use super::driver::VideoSink;
+use super::framebuffer::Framebuffer;
+use super::threading::FrameSchedule;
+use super::threading::Frequency;
+use super::threading::Ratio;
+use super::threading::Speed;
+use super::threading::Thread;
+use super::threading::ThreadRunner;
+use super::timers::BEEP_FREQUENCY;
+use super::timers::ToneEvent;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+pub const CPU_FREQUENCY: f64 = 500.0;
+pub const TIMER_FREQUENCY: f64 = 60.0;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// Owns the whole emulator state, drivers are only borrowed when exchanging frames, keys and sounds
+#[derive(Clone)]
+pub struct Machine {
+    runner: ThreadRunner<Cpu>,
+    timer_frequency: f64,
+    tone_started: u64,
+    audio_tick: u64,
+    frame_remainder: u64,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        return Self::with_frequencies(CPU_FREQUENCY, TIMER_FREQUENCY);
+    }
+
+    pub fn with_frequencies(cpu_frequency: f64, timer_frequency: f64) -> Self {
+        return Self::with_cpu(Cpu::new(timer_frequency), cpu_frequency, timer_frequency);
+    }
+
+    pub fn with_cpu(cpu: Cpu, cpu_frequency: f64, timer_frequency: f64) -> Self {
+        let frequency = Frequency::new(cpu_frequency, 1.0);
+        let sub_frequency = frequency.get_sub_frequency(timer_frequency, 1.0);
+        let mut threads = vec![Thread::new(frequency); THREAD_COUNT];
+        threads[DELAY_TIMER_THREAD] = Thread::new(sub_frequency);
+        threads[SOUND_TIMER_THREAD] = Thread::new(sub_frequency);
+        threads[FRAME_THREAD] = Thread::new(sub_frequency);
+        let mut runner = ThreadRunner::new(frequency, threads, cpu);
+        runner.reset();
+        return Self {
+            runner: runner,
+            timer_frequency: timer_frequency,
+            tone_started: 0,
+            audio_tick: 0,
+            frame_remainder: 0,
+        };
+    }
+
+    pub fn load_rom(&mut self, rom: Vec<u8>) {
+        self.runner.processors_mut().load(rom);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.runner.reset();
+        self.frame_remainder = 0;
+        if self.runner.schedule().is_some() {
+            self.cpu_mut().open_frame();
+        }
+    }
+
+    pub fn instructions_per_frame(&self) -> Option<u32> {
+        return self.runner.schedule().map(|schedule| schedule.executions[CPU_THREAD]);
+    }
+
+    // Executes exactly that many instructions, then ticks the timers and ends the frame, instead of following the cpu frequency
+    pub fn set_instructions_per_frame(&mut self, instructions: Option<u32>) {
+        let schedule = instructions.map(|instructions| {
+            let mut executions = vec![1; THREAD_COUNT];
+            executions[CPU_THREAD] = instructions;
+            FrameSchedule {
+                frame_rate: self.timer_frequency,
+                executions: executions,
+            }
+        });
+        self.runner.set_schedule(schedule);
+        if instructions.is_some() {
+            self.cpu_mut().open_frame();
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        return self.runner.processors();
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        return self.runner.processors_mut();
+    }
+
+    pub fn speed(&self) -> Speed {
+        return self.runner.speed();
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.runner.set_speed(speed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.runner.is_paused();
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.runner.set_paused(paused);
+    }
+
+    // Host time, scaled by the speed
+    #[cfg(feature = "std")]
+    pub fn run_for(&mut self, elapsed: Duration) {
+        if self.cpu().is_halted() {
+            return;
+        }
+        self.runner.tick(elapsed);
+    }
+
+    // Time does not flow while the cpu is halted, timers included
+    pub fn run_for_nanos(&mut self, elapsed: u64) {
+        if self.cpu().is_halted() {
+            return;
+        }
+        self.runner.tick_nanos(elapsed);
+    }
+
+    // Exactly one frame of emulated time, also while paused to advance frame by frame
+    pub fn run_frame(&mut self) {
+        if self.cpu().is_halted() {
+            return;
+        }
+        if self.runner.schedule().is_some() {
+            self.runner.run_frames(1);
+            return;
+        }
+        // Frame lengths are rounded down, the remainder goes to the next frame
+        let frame_rate = Ratio::from_f64(self.timer_frequency);
+        let total = NANOS_PER_SEC * frame_rate.denominator() + self.frame_remainder;
+        self.frame_remainder = total % frame_rate.numerator();
+        self.runner.run_nanos(total / frame_rate.numerator());
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.cpu_mut().set_key(key, true);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.cpu_mut().set_key(key, false);
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        return self.cpu().frame();
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        return self.cpu().frame_count();
+    }
+
+    pub fn read_keypad(&mut self, keypad: &mut dyn Keypad) {
+        for key in 0..KEY_COUNT as u8 {
+            let down = keypad.is_key_down(key);
+            self.cpu_mut().set_key(key, down);
+        }
+    }
+
+    // Hands the frame to the video sink when a new one is ready, only the rows changed since the previous one are marked dirty
+    pub fn present(&mut self, video: &mut dyn VideoSink) -> bool {
+        if self.cpu().is_halted() {
+            self.cpu_mut().sync_frame();
+        }
+        if !self.cpu_mut().take_frame_ready() {
+            return false;
+        }
+        video.fill_buffer(self.framebuffer());
+        self.cpu_mut().frame_mut().clear_dirty();
+        return true;
+    }
+
+    // Events are played in order, the sink is advanced by the emulated time between them
+    pub fn play(&mut self, audio: &mut dyn AudioSink) {
+        for event in self.cpu_mut().take_tone_events() {
+            self.advance_audio(audio, event.tick());
+            match event {
+                ToneEvent::On(tick) => {
+                    self.tone_started = tick;
+                    audio.tone_on(BEEP_FREQUENCY);
+                }
+                ToneEvent::Off(tick) => audio.tone_off(self.cpu().ticks_to_duration(tick - self.tone_started)),
+                ToneEvent::Pattern(_, pattern) => audio.set_pattern(pattern),
+            }
+        }
+        self.advance_audio(audio, self.cpu().timer_ticks());
+    }
+
+    fn advance_audio(&mut self, audio: &mut dyn AudioSink, tick: u64) {
+        if tick > self.audio_tick {
+            audio.advance(self.cpu().ticks_to_duration(tick - self.audio_tick));
+            self.audio_tick = tick;
+        }
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(test)]
+mod machine_tests {
+    use super::Machine;
+    use crate::audio::Recorder;
+    use crate::audio::SAMPLE_RATE;
+    #[cfg(feature = "std")]
+    use crate::cpu::PresentMode;
+    use crate::driver::VideoSink;
+    use crate::framebuffer::Framebuffer;
+    #[cfg(feature = "std")]
+    use crate::threading::Speed;
+    use alloc::vec;
+    #[cfg(feature = "std")]
+    use alloc::vec::Vec;
+    #[cfg(feature = "std")]
+    use core::time::Duration;
+
+    fn assert_send<T: Send>() {}
+
+    struct FakeVideo(usize);
+
+    impl VideoSink for FakeVideo {
+        fn fill_buffer(&mut self, _framebuffer: &Framebuffer) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn given_machine_should_be_send() {
+        assert_send::<Machine>();
+    }
+
+    #[test]
+    fn given_rom_when_run_frame_should_execute_instructions() {
+        let mut machine = Machine::new();
+        // LD V0, 0x01 ; ADD V0, 0x01 ; JP 0x202
+        machine.load_rom(vec![0x60, 0x01, 0x70, 0x01, 0x12, 0x02]);
+
+        machine.run_frame();
+
+        assert!(machine.cpu().v(0) > 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_clone_when_run_should_not_change_original() {
+        let mut machine = Machine::new();
+        machine.load_rom(vec![0x70, 0x01, 0x12, 0x00]);
+        let snapshot = machine.clone();
+
+        machine.run_for(Duration::from_millis(100));
+
+        assert_eq!(snapshot.cpu().v(0), 0);
+        assert_ne!(machine.cpu().v(0), 0);
+    }
+
+    #[test]
+    fn given_pressed_key_when_wait_key_should_store_key() {
+        let mut machine = Machine::new();
+        // LD V1, K ; JP 0x202
+        machine.load_rom(vec![0xF1, 0x0A, 0x12, 0x02]);
+
+        machine.press_key(0x7);
+        machine.run_frame();
+
+        assert_eq!(machine.cpu().v(1), 0x7);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_one_second_when_run_should_count_frames_and_present_once() {
+        let mut machine = Machine::new();
+        machine.load_rom(vec![0x12, 0x00]);
+        let mut video = FakeVideo(0);
+        machine.present(&mut video);
+
+        // The 60th frame ends exactly at one second, it is counted by the next cpu tick
+        machine.run_for(Duration::from_secs(1) + Duration::from_millis(2));
+
+        assert_eq!(machine.frame_count(), 60);
+        assert!(machine.present(&mut video));
+        assert!(!machine.present(&mut video));
+        assert_eq!(video.0, 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_frame_mode_when_sprite_drawn_should_present_at_end_of_frame() {
+        let mut machine = Machine::new();
+        // LD F, V0 ; DRW V0, V0, 5 ; JP 0x204
+        machine.load_rom(vec![0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04]);
+        machine.present(&mut FakeVideo(0));
+
+        machine.run_for(Duration::from_millis(10));
+        assert!(!machine.framebuffer().get(0, 0, 0));
+        machine.run_for(Duration::from_millis(10));
+
+        assert!(machine.framebuffer().get(0, 0, 0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_immediate_mode_when_sprite_drawn_should_present_right_away() {
+        let mut machine = Machine::new();
+        machine.cpu_mut().set_present_mode(PresentMode::Immediate);
+        machine.load_rom(vec![0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04]);
+        machine.present(&mut FakeVideo(0));
+
+        machine.run_for(Duration::from_millis(10));
+
+        assert!(machine.framebuffer().get(0, 0, 0));
+        assert!(machine.present(&mut FakeVideo(0)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_non_erasing_mode_when_sprite_erased_should_present_last_non_erasing_draw() {
+        let mut machine = Machine::new();
+        machine.cpu_mut().set_present_mode(PresentMode::NonErasing);
+        // LD F, V0 ; DRW V0, V0, 5 ; DRW V0, V0, 5 ; JP 0x206
+        machine.load_rom(vec![0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x06]);
+        machine.present(&mut FakeVideo(0));
+
+        machine.run_for(Duration::from_millis(20));
+
+        assert!(!machine.cpu().display().get(0, 0, 0));
+        assert!(machine.framebuffer().get(0, 0, 0));
+        assert!(machine.present(&mut FakeVideo(0)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_sound_timer_when_play_should_turn_tone_on_then_off() {
+        struct FakeAudio(Vec<(u32, Option<Duration>)>);
+        impl crate::driver::AudioSink for FakeAudio {
+            fn tone_on(&mut self, frequency: u32) {
+                self.0.push((frequency, None));
+            }
+
+            fn tone_off(&mut self, length: Duration) {
+                self.0.last_mut().unwrap().1 = Some(length);
+            }
+        }
+        let mut machine = Machine::new();
+        // LD V0, 0x06 ; LD ST, V0 ; JP 0x204
+        machine.load_rom(vec![0x60, 0x06, 0xF0, 0x18, 0x12, 0x04]);
+        let mut audio = FakeAudio(Vec::new());
+
+        machine.run_frame();
+        machine.play(&mut audio);
+        assert_eq!(audio.0, vec![(800, None)]);
+
+        machine.run_for(Duration::from_millis(200));
+        machine.play(&mut audio);
+        assert_eq!(audio.0, vec![(800, Some(Duration::from_millis(100)))]);
+    }
+
+    #[test]
+    fn given_recorder_when_play_every_frame_should_record_in_emulated_time() {
+        let mut machine = Machine::new();
+        // LD V0, 0x06 ; LD ST, V0 ; JP 0x204 plays 0.1 s of tone
+        machine.load_rom(vec![0x60, 0x06, 0xF0, 0x18, 0x12, 0x04]);
+        let mut recorder = Recorder::new(SAMPLE_RATE);
+
+        for _ in 0..60 {
+            machine.run_frame();
+            machine.play(&mut recorder);
+        }
+
+        let tone = recorder.samples().iter().filter(|sample| **sample != 0).count();
+        assert_eq!(recorder.samples().len(), SAMPLE_RATE as usize);
+        assert_eq!(tone, SAMPLE_RATE as usize / 10);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_paused_machine_when_run_should_only_advance_frames() {
+        let mut machine = Machine::new();
+        machine.load_rom(vec![0x12, 0x00]);
+        machine.set_paused(true);
+
+        machine.run_for(Duration::from_millis(100));
+        assert_eq!(machine.cpu().timer_ticks(), 0);
+
+        machine.run_frame();
+        assert_eq!(machine.cpu().timer_ticks(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_speed_when_run_should_scale_emulated_time() {
+        let mut slow = Machine::new();
+        let mut unlimited = Machine::new();
+        slow.load_rom(vec![0x12, 0x00]);
+        unlimited.load_rom(vec![0x12, 0x00]);
+        slow.set_speed(Speed::Multiplier(0.5));
+        unlimited.set_speed(Speed::Unlimited);
+
+        slow.run_for(Duration::from_millis(200));
+        unlimited.run_for(Duration::from_millis(10));
+
+        assert_eq!(slow.cpu().timer_ticks(), 6);
+        assert!(unlimited.cpu().timer_ticks() > 60);
+    }
+
+    #[test]
+    fn given_instructions_per_frame_when_run_frames_should_be_deterministic() {
+        let mut machine = Machine::new();
+        machine.set_instructions_per_frame(Some(15));
+        // ADD V0, 0x01 ; LD DT, V0 ; JP 0x200
+        machine.load_rom(vec![0x70, 0x01, 0xF0, 0x15, 0x12, 0x00]);
+        let mut video = FakeVideo(0);
+
+        for _ in 0..4 {
+            machine.run_frame();
+            machine.present(&mut video);
+        }
+
+        assert_eq!(machine.cpu().v(0), 20);
+        assert_eq!(machine.cpu().delay_timer(), 19);
+        assert_eq!(machine.frame_count(), 4);
+        assert_eq!(machine.instructions_per_frame(), Some(15));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_instructions_per_frame_when_run_for_should_run_whole_frames() {
+        let mut machine = Machine::new();
+        machine.set_instructions_per_frame(Some(10));
+        machine.load_rom(vec![0x70, 0x01, 0x12, 0x00]);
+
+        machine.run_for(Duration::from_millis(25));
+        machine.run_for(Duration::from_millis(25));
+
+        assert_eq!(machine.frame_count(), 3);
+        assert_eq!(machine.cpu().v(0), 15);
+    }
+}
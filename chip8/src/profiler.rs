@@ -0,0 +1,279 @@
+#[cfg(feature = "std")]
+use super::disasm::disassemble;
+use super::disasm::opcode_class;
+use super::trace::TraceEntry;
+use super::trace::Tracer;
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+#[cfg(feature = "std")]
+const ROOT_FRAME: &str = "main";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AddressStats {
+    pub opcode: u16,
+    pub count: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+pub struct Profiler {
+    total: u64,
+    addresses: BTreeMap<u16, AddressStats>,
+    classes: BTreeMap<&'static str, u64>,
+    subroutines: BTreeMap<u16, SubroutineStats>,
+    folded: BTreeMap<Vec<u16>, u64>,
+    stack: Vec<u16>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        return Self {
+            total: 0,
+            addresses: BTreeMap::new(),
+            classes: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            folded: BTreeMap::new(),
+            stack: Vec::new(),
+        };
+    }
+
+    pub fn total(&self) -> u64 {
+        return self.total;
+    }
+
+    pub fn address(&self, address: u16) -> Option<AddressStats> {
+        return self.addresses.get(&address).copied();
+    }
+
+    pub fn class(&self, class: &str) -> u64 {
+        return self.classes.get(class).copied().unwrap_or(0);
+    }
+
+    pub fn subroutine(&self, address: u16) -> Option<SubroutineStats> {
+        return self.subroutines.get(&address).copied();
+    }
+
+    pub fn hot_spots(&self, count: usize) -> Vec<(u16, AddressStats)> {
+        let mut hot_spots: Vec<(u16, AddressStats)> = self
+            .addresses
+            .iter()
+            .map(|(address, stats)| (*address, *stats))
+            .collect();
+        hot_spots.sort_by(|left, right| right.1.count.cmp(&left.1.count).then(left.0.cmp(&right.0)));
+        hot_spots.truncate(count);
+        return hot_spots;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_report<W: Write>(&self, writer: &mut W, count: usize) -> std::io::Result<()> {
+        writeln!(writer, "Instructions: {}", self.total)?;
+
+        writeln!(writer)?;
+        writeln!(writer, "Hot spots:")?;
+        for (address, stats) in self.hot_spots(count) {
+            writeln!(
+                writer,
+                "  {:03X}  {:>10}  {:>6.2}%  {:04X}  {}",
+                address,
+                stats.count,
+                self.percent(stats.count),
+                stats.opcode,
+                disassemble(stats.opcode)
+            )?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "Opcodes:")?;
+        let mut classes: Vec<(&&str, &u64)> = self.classes.iter().collect();
+        classes.sort_by(|left, right| right.1.cmp(left.1).then(left.0.cmp(right.0)));
+        for (class, executions) in classes {
+            writeln!(writer, "  {}  {:>10}  {:>6.2}%", class, executions, self.percent(*executions))?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "Subroutines:           calls   inclusive   exclusive")?;
+        let mut subroutines: Vec<(&u16, &SubroutineStats)> = self.subroutines.iter().collect();
+        subroutines.sort_by(|left, right| right.1.inclusive.cmp(&left.1.inclusive).then(left.0.cmp(right.0)));
+        for (address, stats) in subroutines.iter().take(count) {
+            writeln!(
+                writer,
+                "  {:03X}            {:>10}  {:>10}  {:>10}",
+                address, stats.calls, stats.inclusive, stats.exclusive
+            )?;
+        }
+        return Ok(());
+    }
+
+    // One "main;0x2A0;0x31C count" line per distinct call stack, as expected by flamegraph.pl
+    #[cfg(feature = "std")]
+    pub fn write_folded<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for (stack, count) in self.folded.iter() {
+            let mut line = String::from(ROOT_FRAME);
+            for address in stack {
+                line.push_str(&format!(";0x{:03X}", address));
+            }
+            writeln!(writer, "{} {}", line, count)?;
+        }
+        return Ok(());
+    }
+
+    #[cfg(feature = "std")]
+    fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        return 100.0 * count as f64 / self.total as f64;
+    }
+
+    fn attribute_to_stack(&mut self) {
+        match self.folded.get_mut(self.stack.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(self.stack.clone(), 1);
+            }
+        }
+
+        for (depth, address) in self.stack.iter().enumerate() {
+            // Recursive frames only count once toward the inclusive time
+            if self.stack[..depth].contains(address) {
+                continue;
+            }
+            self.subroutines.entry(*address).or_default().inclusive += 1;
+        }
+
+        if let Some(address) = self.stack.last() {
+            self.subroutines.entry(*address).or_default().exclusive += 1;
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Tracer for Profiler {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.total += 1;
+
+        let stats = self.addresses.entry(entry.program_counter).or_default();
+        stats.opcode = entry.opcode;
+        stats.count += 1;
+
+        *self.classes.entry(opcode_class(entry.opcode)).or_default() += 1;
+
+        self.attribute_to_stack();
+
+        if entry.opcode >> 12 == 0x2 {
+            let address = entry.opcode & 0x0FFF;
+            self.subroutines.entry(address).or_default().calls += 1;
+            self.stack.push(address);
+        } else if entry.opcode == 0x00EE {
+            self.stack.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod profiler_tests {
+    use super::Profiler;
+    use super::SubroutineStats;
+    use crate::trace::TraceEntry;
+    use crate::trace::Tracer;
+    #[cfg(feature = "std")]
+    use alloc::string::String;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn execute(profiler: &mut Profiler, program: &[(u16, u16)]) {
+        for (address, opcode) in program {
+            profiler.trace(&TraceEntry {
+                program_counter: *address,
+                opcode: *opcode,
+                v: [0; 16],
+                i: 0,
+                stack_pointer: 0,
+                delay_timer: 0,
+                sound_timer: 0,
+            });
+        }
+    }
+
+    fn call_twice() -> Vec<(u16, u16)> {
+        return vec![
+            (0x200, 0x2300),
+            (0x300, 0x7001),
+            (0x302, 0x00EE),
+            (0x202, 0x2300),
+            (0x300, 0x7001),
+            (0x302, 0x00EE),
+            (0x204, 0x1204),
+        ];
+    }
+
+    #[test]
+    fn given_program_when_trace_should_count_addresses_and_classes() {
+        let mut profiler = Profiler::new();
+
+        execute(&mut profiler, &call_twice());
+
+        assert_eq!(profiler.total(), 7);
+        assert_eq!(profiler.address(0x300).unwrap().count, 2);
+        assert_eq!(profiler.class("2nnn"), 2);
+        assert_eq!(profiler.class("7xnn"), 2);
+        assert_eq!(profiler.class("1nnn"), 1);
+    }
+
+    #[test]
+    fn given_calls_when_trace_should_attribute_cycles_to_subroutines() {
+        let mut profiler = Profiler::new();
+
+        execute(&mut profiler, &call_twice());
+
+        assert_eq!(
+            profiler.subroutine(0x300),
+            Some(SubroutineStats {
+                calls: 2,
+                inclusive: 4,
+                exclusive: 4
+            })
+        );
+    }
+
+    #[test]
+    fn given_program_when_hot_spots_should_sort_by_count() {
+        let mut profiler = Profiler::new();
+
+        execute(&mut profiler, &call_twice());
+
+        let hot_spots = profiler.hot_spots(2);
+        assert_eq!(hot_spots.len(), 2);
+        assert_eq!(hot_spots[0].0, 0x300);
+        assert_eq!(hot_spots[1].0, 0x302);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_calls_when_write_folded_should_write_stacks() {
+        let mut profiler = Profiler::new();
+        execute(&mut profiler, &call_twice());
+        let mut folded = Vec::new();
+
+        profiler.write_folded(&mut folded).unwrap();
+
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 3\nmain;0x300 4\n");
+    }
+}
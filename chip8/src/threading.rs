@@ -1,8 +1,196 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::time::Duration;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+pub const MIN_SPEED: f64 = 0.25;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const SYNC_NANOS: u64 = NANOS_PER_SEC;
+const UNLIMITED_SLICE_NANOS: u64 = 1_000_000;
+// Host time spent per call at unlimited speed, one 60 Hz display frame
+const UNLIMITED_BUDGET_NANOS: u64 = NANOS_PER_SEC / 60;
+const MAX_DENOMINATOR: u64 = 1 << 20;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    Multiplier(f64),
+    Unlimited,
+}
+
+// Monotonic host time in nanoseconds, from an arbitrary origin
+pub trait Clock {
+    fn now_nanos(&self) -> u64;
+}
+
+#[cfg(feature = "std")]
+pub struct MonotonicClock {
+    origin: Instant,
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    pub fn new() -> Self {
+        return Self {
+            origin: Instant::now(),
+        };
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    fn now_nanos(&self) -> u64 {
+        return self.origin.elapsed().as_nanos() as u64;
+    }
+}
+
+// Fake clock for tests, only moves when advanced, or by a fixed step every time it is read
+pub struct ManualClock {
+    nanos: Cell<u64>,
+    step: u64,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        return Self::with_step(Duration::ZERO);
+    }
+
+    pub fn with_step(step: Duration) -> Self {
+        return Self {
+            nanos: Cell::new(0),
+            step: step.as_nanos() as u64,
+        };
+    }
+
+    pub fn advance(&self, elapsed: Duration) {
+        self.nanos.set(self.nanos.get() + elapsed.as_nanos() as u64);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_nanos(&self) -> u64 {
+        let now = self.nanos.get();
+        self.nanos.set(now + self.step);
+        return now;
+    }
+}
+
+// Host time elapsed between two laps
+pub struct Stopwatch<C: Clock> {
+    clock: C,
+    last: u64,
+}
+
+impl<C: Clock> Stopwatch<C> {
+    pub fn new(clock: C) -> Self {
+        let last = clock.now_nanos();
+        return Self {
+            clock: clock,
+            last: last,
+        };
+    }
+
+    pub fn clock(&self) -> &C {
+        return &self.clock;
+    }
+
+    pub fn lap(&mut self) -> Duration {
+        let now = self.clock.now_nanos();
+        let elapsed = now.saturating_sub(self.last);
+        self.last = now;
+        return Duration::from_nanos(elapsed);
+    }
+}
+
+// Exact fraction, frequencies and dividers are converted once so that clocks only add integers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ratio {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl Ratio {
+    pub fn new(numerator: u64, denominator: u64) -> Self {
+        let divisor = gcd(numerator, denominator).max(1);
+        return Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        };
+    }
+
+    // Closest fraction with a bounded denominator (continued fraction), 500.0 / 60.0 gives back 25 / 3
+    pub fn from_f64(value: f64) -> Self {
+        let (mut numerator, mut previous_numerator) = (1u64, 0u64);
+        let (mut denominator, mut previous_denominator) = (0u64, 1u64);
+        let mut x = value.max(0.0);
+        loop {
+            let whole = x as u64;
+            let next_denominator = whole * denominator + previous_denominator;
+            if next_denominator > MAX_DENOMINATOR {
+                break;
+            }
+            let next_numerator = whole * numerator + previous_numerator;
+            (previous_numerator, numerator) = (numerator, next_numerator);
+            (previous_denominator, denominator) = (denominator, next_denominator);
+
+            let fraction = x - whole as f64;
+            if fraction < 1e-9 {
+                break;
+            }
+            x = 1.0 / fraction;
+        }
+        return Ratio::new(numerator, denominator.max(1));
+    }
 
-const SYNC_DURATION: Duration = Duration::from_secs(1);
+    pub fn numerator(&self) -> u64 {
+        return self.numerator;
+    }
+
+    pub fn denominator(&self) -> u64 {
+        return self.denominator;
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        return self.numerator as f64 / self.denominator as f64;
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    return if b == 0 { a } else { gcd(b, a % b) };
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    return a / gcd(a, b) * b;
+}
+
+// Converts a quantity given in nanoseconds to whole units at an exact rate, keeping the remainder for the next call
+#[derive(Copy, Clone, Debug, Default)]
+struct Accumulator {
+    remainder: u128,
+}
+
+impl Accumulator {
+    fn take(&mut self, nanos: u64, rate: Ratio) -> u64 {
+        let scale = NANOS_PER_SEC as u128 * rate.denominator as u128;
+        let total = self.remainder + nanos as u128 * rate.numerator as u128;
+        self.remainder = total % scale;
+        return (total / scale) as u64;
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct Frequency {
@@ -23,81 +211,208 @@ impl Frequency {
     }
 }
 
+// Fixed number of executions of each thread per frame, in thread order, instead of the thread frequencies
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameSchedule {
+    pub frame_rate: f64,
+    pub executions: Vec<u32>,
+}
+
 pub trait Processor {
     fn execute(&mut self) -> u8;
     fn reset(&mut self);
 }
 
+// Processors owned by a ThreadRunner, addressed by thread index so that several threads can share one owner
+pub trait Processors {
+    fn execute_thread(&mut self, thread: usize) -> u8;
+    fn reset_thread(&mut self, thread: usize);
+}
+
+impl<P: Processor> Processors for Vec<P> {
+    fn execute_thread(&mut self, thread: usize) -> u8 {
+        return self[thread].execute();
+    }
+
+    fn reset_thread(&mut self, thread: usize) {
+        self[thread].reset();
+    }
+}
+
+// The clock counts main ticks in units of 1 / divider denominator
+#[derive(Copy, Clone)]
 pub struct Thread {
-    clock: f64,
-    frequency: Frequency,
-    processor: Rc<RefCell<dyn Processor>>,
+    clock: i64,
+    divider: Ratio,
 }
 
 impl Thread {
-    pub fn new(frequency: Frequency, processor: Rc<RefCell<dyn Processor>>) -> Self {
+    pub fn new(frequency: Frequency) -> Self {
         return Self {
-            clock: 0.0,
-            frequency: frequency,
-            processor: processor,
+            clock: 0,
+            divider: Ratio::from_f64(frequency.divider),
         };
     }
 
-    fn tick(&mut self) {
-        let tick = self.processor.borrow_mut().execute();
-        self.clock += tick as f64 * self.frequency.divider;
+    // In main ticks
+    pub fn clock(&self) -> f64 {
+        return self.clock as f64 / self.divider.denominator as f64;
+    }
+
+    // Compares exactly with a main clock given in units of 1 / denominator
+    fn is_before(&self, clock: i64, denominator: u64) -> bool {
+        return (self.clock as i128) * (denominator as i128) < (clock as i128) * (self.divider.denominator as i128);
+    }
+
+    fn tick(&mut self, index: usize, processors: &mut dyn Processors) {
+        let tick = processors.execute_thread(index);
+        self.clock += tick as i64 * self.divider.numerator as i64;
     }
 
-    fn reset(&mut self) {
-        self.processor.borrow_mut().reset();
-        self.clock = 0.0;
+    fn reset(&mut self, index: usize, processors: &mut dyn Processors) {
+        processors.reset_thread(index);
+        self.clock = 0;
     }
 
-    fn synchronize_clock(&mut self, main_clock: f64) {
-        self.clock -= main_clock;
+    fn synchronize_clock(&mut self, main_clock: i64, denominator: u64) {
+        let main_clock = main_clock as i128 * self.divider.denominator as i128 / denominator as i128;
+        self.clock -= main_clock as i64;
     }
 }
 
-pub struct ThreadRunner {
-    clock: f64,
-    elapsed: Duration,
-    incomplete_tick: f64,
-    frequency: Frequency,
+#[derive(Clone)]
+pub struct ThreadRunner<P: Processors> {
+    clock: i64,
+    elapsed: u64,
+    ticks: Accumulator,
+    speed_remainder: u128,
+    rate: Ratio,
+    divider: Ratio,
     threads: Vec<Thread>,
+    processors: P,
+    speed: Speed,
+    paused: bool,
+    schedule: Option<FrameSchedule>,
 }
 
-impl ThreadRunner {
-    pub fn new(frequency: Frequency, threads: Vec<Thread>) -> Self {
+impl<P: Processors> ThreadRunner<P> {
+    pub fn new(frequency: Frequency, threads: Vec<Thread>, processors: P) -> Self {
         return Self {
-            clock: 0.0,
-            elapsed: Duration::ZERO,
-            incomplete_tick: 0.0,
-            frequency: frequency,
+            clock: 0,
+            elapsed: 0,
+            ticks: Accumulator::default(),
+            speed_remainder: 0,
+            rate: Ratio::from_f64(frequency.value),
+            divider: Ratio::from_f64(frequency.divider),
             threads: threads,
+            processors: processors,
+            speed: Speed::Multiplier(1.0),
+            paused: false,
+            schedule: None,
+        };
+    }
+
+    // In main ticks, since the last synchronization
+    pub fn clock(&self) -> f64 {
+        return self.clock as f64 / self.divider.denominator as f64;
+    }
+
+    pub fn processors(&self) -> &P {
+        return &self.processors;
+    }
+
+    pub fn processors_mut(&mut self) -> &mut P {
+        return &mut self.processors;
+    }
+
+    pub fn speed(&self) -> Speed {
+        return self.speed;
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = match speed {
+            Speed::Multiplier(multiplier) => Speed::Multiplier(multiplier.max(MIN_SPEED)),
+            Speed::Unlimited => Speed::Unlimited,
         };
+        self.speed_remainder = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.paused;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn schedule(&self) -> Option<&FrameSchedule> {
+        return self.schedule.as_ref();
     }
 
+    pub fn set_schedule(&mut self, schedule: Option<FrameSchedule>) {
+        self.schedule = schedule;
+        self.ticks = Accumulator::default();
+    }
+
+    #[cfg(feature = "std")]
     pub fn tick(&mut self, elapsed: Duration) {
-        self.incomplete_tick += self.frequency.value * elapsed.as_secs_f64();
-        let complete_tick_f64 = self.incomplete_tick.trunc();
-        self.incomplete_tick -= complete_tick_f64;
-        let complete_tick = complete_tick_f64 as u64;
+        self.tick_with_clock(elapsed.as_nanos() as u64, &MonotonicClock::new());
+    }
 
+    // Host time scaled by the speed, without a clock unlimited speed runs as 1x
+    pub fn tick_nanos(&mut self, elapsed: u64) {
+        if self.paused {
+            return;
+        }
+        match self.speed {
+            Speed::Multiplier(multiplier) => {
+                let multiplier = Ratio::from_f64(multiplier);
+                let total = self.speed_remainder + elapsed as u128 * multiplier.numerator as u128;
+                self.speed_remainder = total % multiplier.denominator as u128;
+                self.run_nanos((total / multiplier.denominator as u128) as u64);
+            }
+            Speed::Unlimited => self.run_nanos(elapsed),
+        }
+    }
+
+    // Unlimited speed runs slices of emulated time for a fixed budget of host time, not for `elapsed`:
+    // the caller's frame includes this loop, so using its length would make every frame longer
+    pub fn tick_with_clock(&mut self, elapsed: u64, clock: &dyn Clock) {
+        if self.paused || self.speed != Speed::Unlimited {
+            self.tick_nanos(elapsed);
+            return;
+        }
+        let start = clock.now_nanos();
+        while clock.now_nanos() - start < UNLIMITED_BUDGET_NANOS {
+            self.run_nanos(UNLIMITED_SLICE_NANOS);
+        }
+    }
+
+    // Emulated time, whatever the speed and the pause state
+    pub fn run_nanos(&mut self, elapsed: u64) {
+        if let Some(schedule) = &self.schedule {
+            let frames = self.ticks.take(elapsed, Ratio::from_f64(schedule.frame_rate));
+            self.run_frames(frames);
+            return;
+        }
+
+        let complete_tick = self.ticks.take(elapsed, self.rate);
+        let denominator = self.divider.denominator;
         for _i in 0..complete_tick {
-            let next_clock = self.clock + self.frequency.divider;
+            let next_clock = self.clock + self.divider.numerator as i64;
 
             loop {
-                let mut latest_processor_clock = f64::MAX;
+                let mut done = true;
 
-                for thread in self.threads.iter_mut() {
-                    if thread.clock < next_clock {
-                        thread.tick();
+                for (index, thread) in self.threads.iter_mut().enumerate() {
+                    if thread.is_before(next_clock, denominator) {
+                        thread.tick(index, &mut self.processors);
                     }
 
-                    latest_processor_clock = latest_processor_clock.min(thread.clock);
+                    done &= !thread.is_before(next_clock, denominator);
                 }
 
-                if latest_processor_clock >= next_clock {
+                if done {
                     break;
                 }
             }
@@ -106,24 +421,43 @@ impl ThreadRunner {
         }
 
         self.elapsed += elapsed;
-        if self.elapsed < SYNC_DURATION {
+        if self.elapsed < SYNC_NANOS {
             return;
         }
 
-        self.elapsed -= SYNC_DURATION;
+        self.elapsed -= SYNC_NANOS;
 
+        // Only subtract a main clock that every thread clock can represent, the rest is kept
+        let step = self.threads.iter().fold(1, |step, thread| {
+            lcm(step, denominator / gcd(denominator, thread.divider.denominator))
+        });
+        let sync = self.clock - self.clock % step as i64;
         for thread in self.threads.iter_mut() {
-            thread.synchronize_clock(self.clock);
+            thread.synchronize_clock(sync, denominator);
         }
 
-        self.clock = 0.0;
+        self.clock -= sync;
+    }
+
+    // Only used with a frame schedule, whatever the speed and the pause state
+    pub fn run_frames(&mut self, count: u64) {
+        let Some(schedule) = &self.schedule else {
+            return;
+        };
+        for _i in 0..count {
+            for (index, executions) in schedule.executions.iter().enumerate() {
+                for _j in 0..*executions {
+                    self.processors.execute_thread(index);
+                }
+            }
+        }
     }
 
     pub fn reset(&mut self) {
-        self.elapsed = Duration::ZERO;
-        self.incomplete_tick = 0.0;
-        for thread in self.threads.iter_mut() {
-            thread.reset();
+        self.elapsed = 0;
+        self.ticks = Accumulator::default();
+        for (index, thread) in self.threads.iter_mut().enumerate() {
+            thread.reset(index, &mut self.processors);
         }
     }
 }
@@ -153,13 +487,21 @@ mod frequency_tests {
 
 #[cfg(test)]
 mod thread_tests {
+    use super::Clock;
+    #[cfg(feature = "std")]
+    use super::FrameSchedule;
     use super::Frequency;
+    use super::ManualClock;
     use super::Processor;
+    use super::Ratio;
+    use super::Speed;
+    use super::Stopwatch;
     use super::Thread;
     use super::ThreadRunner;
-    use std::cell::RefCell;
-    use std::rc::Rc;
-    use std::time::Duration;
+    use super::MIN_SPEED;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::time::Duration;
 
     const EXECUTE_STEP: u8 = 2;
 
@@ -189,75 +531,76 @@ mod thread_tests {
     #[test]
     fn given_thread_when_tick_should_execute() {
         let frequency: Frequency = Frequency::new(500.0, 4.0);
-        let processor = Rc::new(RefCell::new(FakeProcessor::new()));
-        let mut thread = Thread::new(frequency, processor.clone());
+        let mut processors = vec![FakeProcessor::new()];
+        let mut thread = Thread::new(frequency);
 
-        thread.tick();
+        thread.tick(0, &mut processors);
 
-        assert_eq!(processor.borrow().execute_call_count, 1);
+        assert_eq!(processors[0].execute_call_count, 1);
     }
 
     #[test]
     fn given_thread_when_tick_should_inc_clock() {
         let frequency: Frequency = Frequency::new(500.0, 4.0);
-        let processor = Rc::new(RefCell::new(FakeProcessor::new()));
-        let mut thread = Thread::new(frequency, processor);
+        let mut processors = vec![FakeProcessor::new()];
+        let mut thread = Thread::new(frequency);
         let expected_clock = EXECUTE_STEP as f64 * frequency.divider;
 
-        thread.tick();
+        thread.tick(0, &mut processors);
 
-        assert_eq!(thread.clock, expected_clock);
+        assert_eq!(thread.clock(), expected_clock);
     }
 
     #[test]
     fn given_thread_when_reset_should_reset_state() {
         let frequency: Frequency = Frequency::new(500.0, 4.0);
-        let processor = Rc::new(RefCell::new(FakeProcessor::new()));
-        let mut thread = Thread::new(frequency, processor);
-        thread.tick();
+        let mut processors = vec![FakeProcessor::new()];
+        let mut thread = Thread::new(frequency);
+        thread.tick(0, &mut processors);
 
-        thread.reset();
+        thread.reset(0, &mut processors);
 
-        assert_eq!(thread.clock, 0.0);
+        assert_eq!(thread.clock(), 0.0);
+        assert_eq!(processors[0].execute_call_count, 0);
     }
 
     #[test]
     fn given_thread_when_reset_should_adjust_clock() {
         let frequency: Frequency = Frequency::new(500.0, 4.0);
-        let main_clock = 50.0;
-        let expected_clock = EXECUTE_STEP as f64 * frequency.divider - main_clock;
-        let processor = Rc::new(RefCell::new(FakeProcessor::new()));
-        let mut thread = Thread::new(frequency, processor);
-        thread.tick();
+        let main_clock = 50;
+        let expected_clock = EXECUTE_STEP as f64 * frequency.divider - main_clock as f64;
+        let mut processors = vec![FakeProcessor::new()];
+        let mut thread = Thread::new(frequency);
+        thread.tick(0, &mut processors);
 
-        thread.synchronize_clock(main_clock);
+        thread.synchronize_clock(main_clock, 1);
 
-        assert_eq!(thread.clock, expected_clock);
+        assert_eq!(thread.clock(), expected_clock);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn given_runner_when_tick_should_increment_clock() {
         let frequency: Frequency = Frequency::new(250.0, 1.0);
-        let mut runner = ThreadRunner::new(frequency, Vec::new());
+        let mut runner = ThreadRunner::new(frequency, Vec::new(), Vec::<FakeProcessor>::new());
         let expected_tick = 5.0;
         let expected_clock = expected_tick * frequency.divider;
         let duration_secs = expected_tick / frequency.value;
 
         runner.tick(Duration::from_secs_f64(duration_secs));
 
-        assert_eq!(runner.clock, expected_clock);
+        assert_eq!(runner.clock(), expected_clock);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn fiven_runner_when_tick_should_tick_threads() {
         let frequency: Frequency = Frequency::new(250.0, 1.0);
         let sub_frequency = frequency.get_sub_frequency(50.0, 1.0);
         let mut runner = ThreadRunner::new(
             frequency,
-            vec![
-                Thread::new(frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-                Thread::new(sub_frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-            ],
+            vec![Thread::new(frequency), Thread::new(sub_frequency)],
+            vec![FakeProcessor::new(), FakeProcessor::new()],
         );
         let expected_tick1 = 6.0;
         let expected_tick2 = 2.0;
@@ -267,45 +610,162 @@ mod thread_tests {
 
         runner.tick(Duration::from_secs_f64(duration_secs));
 
-        assert_eq!(runner.threads[0].clock, expected_clock1);
-        assert_eq!(runner.threads[1].clock, expected_clock2);
+        assert_eq!(runner.threads[0].clock(), expected_clock1);
+        assert_eq!(runner.threads[1].clock(), expected_clock2);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn fiven_runner_when_tick_should_reset_threads() {
         let frequency: Frequency = Frequency::new(250.0, 1.0);
         let sub_frequency = frequency.get_sub_frequency(50.0, 1.0);
         let mut runner = ThreadRunner::new(
             frequency,
-            vec![
-                Thread::new(frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-                Thread::new(sub_frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-            ],
+            vec![Thread::new(frequency), Thread::new(sub_frequency)],
+            vec![FakeProcessor::new(), FakeProcessor::new()],
         );
         let duration_secs = 5.0 / frequency.value;
         runner.tick(Duration::from_secs_f64(duration_secs));
 
         runner.reset();
 
-        assert_eq!(runner.threads[0].clock, 0.0);
-        assert_eq!(runner.threads[1].clock, 0.0);
+        assert_eq!(runner.threads[0].clock(), 0.0);
+        assert_eq!(runner.threads[1].clock(), 0.0);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn fiven_runner_when_tick_should_reset_sync_clocks() {
         let frequency: Frequency = Frequency::new(250.0, 1.0);
         let sub_frequency = frequency.get_sub_frequency(50.0, 1.0);
         let mut runner = ThreadRunner::new(
             frequency,
-            vec![
-                Thread::new(frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-                Thread::new(sub_frequency, Rc::new(RefCell::new(FakeProcessor::new()))),
-            ],
+            vec![Thread::new(frequency), Thread::new(sub_frequency)],
+            vec![FakeProcessor::new(), FakeProcessor::new()],
         );
 
         runner.tick(Duration::from_secs(1));
 
-        assert_eq!(runner.threads[0].clock, 0.0);
-        assert_eq!(runner.threads[1].clock, 0.0);
+        assert_eq!(runner.threads[0].clock(), 0.0);
+        assert_eq!(runner.threads[1].clock(), 0.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_speed_when_tick_should_scale_elapsed_time() {
+        let frequency: Frequency = Frequency::new(250.0, 1.0);
+        let mut runner = ThreadRunner::new(frequency, Vec::new(), Vec::<FakeProcessor>::new());
+        runner.set_speed(Speed::Multiplier(2.0));
+
+        runner.tick(Duration::from_millis(20));
+
+        assert_eq!(runner.clock(), 10.0);
+    }
+
+    #[test]
+    fn given_too_slow_speed_when_set_speed_should_clamp_to_minimum() {
+        let frequency: Frequency = Frequency::new(250.0, 1.0);
+        let mut runner = ThreadRunner::new(frequency, Vec::new(), Vec::<FakeProcessor>::new());
+
+        runner.set_speed(Speed::Multiplier(0.1));
+
+        assert_eq!(runner.speed(), Speed::Multiplier(MIN_SPEED));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_paused_runner_when_tick_should_not_run_but_run_nanos_should() {
+        let frequency: Frequency = Frequency::new(250.0, 1.0);
+        let mut runner = ThreadRunner::new(
+            frequency,
+            vec![Thread::new(frequency)],
+            vec![FakeProcessor::new()],
+        );
+        runner.set_paused(true);
+
+        runner.tick(Duration::from_millis(20));
+        assert_eq!(runner.processors()[0].execute_call_count, 0);
+
+        runner.run_nanos(20_000_000);
+        assert_eq!(runner.processors()[0].execute_call_count, 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_frame_schedule_when_tick_should_execute_threads_per_frame() {
+        let frequency: Frequency = Frequency::new(250.0, 1.0);
+        let mut runner = ThreadRunner::new(
+            frequency,
+            vec![Thread::new(frequency), Thread::new(frequency)],
+            vec![FakeProcessor::new(), FakeProcessor::new()],
+        );
+        runner.set_schedule(Some(FrameSchedule {
+            frame_rate: 50.0,
+            executions: vec![7, 1],
+        }));
+
+        runner.tick(Duration::from_millis(50));
+
+        assert_eq!(runner.processors()[0].execute_call_count, 14);
+        assert_eq!(runner.processors()[1].execute_call_count, 2);
+    }
+
+    #[test]
+    fn given_float_when_from_f64_should_find_exact_ratio() {
+        assert_eq!(Ratio::from_f64(500.0 / 60.0), Ratio::new(25, 3));
+        assert_eq!(Ratio::from_f64(0.25), Ratio::new(1, 4));
+        assert_eq!(Ratio::from_f64(60.0), Ratio::new(60, 1));
+        assert_eq!(Ratio::new(50, 6).to_f64(), 25.0 / 3.0);
+    }
+
+    #[test]
+    fn given_manual_clock_when_lap_should_return_advanced_time() {
+        let mut stopwatch = Stopwatch::new(ManualClock::new());
+
+        stopwatch.clock().advance(Duration::from_millis(16));
+
+        assert_eq!(stopwatch.lap(), Duration::from_millis(16));
+        assert_eq!(stopwatch.lap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn given_uneven_slices_when_tick_should_not_drift() {
+        let frequency: Frequency = Frequency::new(500.0, 1.0);
+        let sub_frequency = frequency.get_sub_frequency(60.0, 1.0);
+        let mut runner = ThreadRunner::new(
+            frequency,
+            vec![Thread::new(frequency), Thread::new(sub_frequency)],
+            vec![FakeProcessor::new(), FakeProcessor::new()],
+        );
+        let mut execute_counts = [0u64; 2];
+
+        // Ten minutes of 7 ms frames, the last one is completed to the minute
+        for _ in 0..85714 {
+            runner.tick_nanos(7_000_000);
+            for (index, count) in execute_counts.iter_mut().enumerate() {
+                *count += runner.processors()[index].execute_call_count as u64;
+                runner.processors_mut()[index].execute_call_count = 0;
+            }
+        }
+        runner.tick_nanos(2_000_000);
+        for (index, count) in execute_counts.iter_mut().enumerate() {
+            *count += runner.processors()[index].execute_call_count as u64;
+        }
+
+        assert_eq!(execute_counts, [600 * 500 / EXECUTE_STEP as u64, 600 * 60 / EXECUTE_STEP as u64]);
+    }
+
+    #[test]
+    fn given_unlimited_speed_when_tick_with_clock_should_run_for_one_display_frame() {
+        let frequency: Frequency = Frequency::new(1000.0, 1.0);
+        let mut runner = ThreadRunner::new(frequency, vec![Thread::new(frequency)], vec![FakeProcessor::new()]);
+        runner.set_speed(Speed::Unlimited);
+        let clock = ManualClock::with_step(Duration::from_millis(1));
+
+        // A long previous frame does not make the busy loop longer
+        runner.tick_with_clock(1_000_000_000, &clock);
+
+        assert_eq!(clock.now_nanos(), 18_000_000);
+        assert_eq!(runner.processors()[0].execute_call_count, 8);
     }
 }
@@ -1,10 +1,32 @@
-use super::driver::Driver;
 use super::threading::Processor;
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::vec::Vec;
+use core::mem;
+use core::time::Duration;
 
-const BEEP_FREQUENCY: u32 = 800;
+pub const BEEP_FREQUENCY: u32 = 800;
+pub const PATTERN_SIZE: usize = 16;
+pub const PATTERN_BITS: usize = 8 * PATTERN_SIZE;
+pub const DEFAULT_PITCH: u8 = 64;
 
+// XO-CHIP 1-bit sample pattern, played from the high bit of the first byte
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AudioPattern {
+    pub buffer: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // Bits played per second
+    pub fn rate(&self) -> f64 {
+        return 4000.0 * libm::exp2((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0);
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        return self.buffer[index / 8] & (0x80 >> (index % 8)) != 0;
+    }
+}
+
+#[derive(Clone)]
 pub struct CpuTimer {
     pub value: u8,
 }
@@ -28,45 +50,218 @@ impl Processor for CpuTimer {
     }
 }
 
+// Transitions of the tone, in sound timer ticks since the timer was created
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneEvent {
+    On(u64),
+    Off(u64),
+    Pattern(u64, Option<AudioPattern>),
+}
+
+impl ToneEvent {
+    pub fn tick(&self) -> u64 {
+        return match self {
+            ToneEvent::On(tick) | ToneEvent::Off(tick) | ToneEvent::Pattern(tick, _) => *tick,
+        };
+    }
+}
+
+// Events are dropped oldest first when nobody takes them
+const MAX_TONE_EVENTS: usize = 64;
+
+#[derive(Clone)]
 pub struct SoundTimer {
-    beep: bool,
-    pub cpu_timer: CpuTimer,
+    cpu_timer: CpuTimer,
     frequency: f64,
-    driver: Rc<RefCell<dyn Driver>>,
+    tick: u64,
+    pattern: Option<[u8; PATTERN_SIZE]>,
+    pitch: u8,
+    events: Vec<ToneEvent>,
 }
 
 impl SoundTimer {
-    pub fn new(frequency: f64, driver: Rc<RefCell<dyn Driver>>) -> Self {
+    pub fn new(frequency: f64) -> Self {
         return Self {
-            beep: false,
             cpu_timer: CpuTimer::new(),
             frequency: frequency,
-            driver: driver,
+            tick: 0,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            events: Vec::new(),
         };
     }
 
-    fn do_beep(&self) {
-        let duration = (1000.0 * self.cpu_timer.value as f64 / self.frequency) as u32;
-        self.driver.borrow_mut().sound_do_beep(BEEP_FREQUENCY, duration);
+    pub fn value(&self) -> u8 {
+        return self.cpu_timer.value;
+    }
+
+    // The tone starts or stops at the current tick, rewriting a running timer only changes its length
+    pub fn set(&mut self, value: u8) {
+        let was_on = self.is_tone_on();
+        self.cpu_timer.value = value;
+        self.update_tone(was_on);
+    }
+
+    pub fn is_tone_on(&self) -> bool {
+        return self.cpu_timer.value > 0;
+    }
+
+    // The pattern replaces the beeper until reset
+    pub fn set_pattern(&mut self, buffer: [u8; PATTERN_SIZE]) {
+        self.pattern = Some(buffer);
+        self.push_event(ToneEvent::Pattern(self.tick, self.pattern()));
+    }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+        if self.pattern.is_some() {
+            self.push_event(ToneEvent::Pattern(self.tick, self.pattern()));
+        }
+    }
+
+    pub fn pattern(&self) -> Option<AudioPattern> {
+        return self.pattern.map(|buffer| AudioPattern {
+            buffer: buffer,
+            pitch: self.pitch,
+        });
+    }
+
+    pub fn tick(&self) -> u64 {
+        return self.tick;
+    }
+
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        return Duration::from_secs_f64(ticks as f64 / self.frequency);
+    }
+
+    pub fn take_events(&mut self) -> Vec<ToneEvent> {
+        return mem::take(&mut self.events);
+    }
+
+    fn update_tone(&mut self, was_on: bool) {
+        let event = match (was_on, self.is_tone_on()) {
+            (false, true) => ToneEvent::On(self.tick),
+            (true, false) => ToneEvent::Off(self.tick),
+            _ => return,
+        };
+        self.push_event(event);
+    }
+
+    fn push_event(&mut self, event: ToneEvent) {
+        if self.events.len() == MAX_TONE_EVENTS {
+            self.events.remove(0);
+        }
+        self.events.push(event);
     }
 }
 
 impl Processor for SoundTimer {
     fn execute(&mut self) -> u8 {
-        if !self.beep && self.cpu_timer.value > 0 {
-            self.do_beep();
-            self.beep = true;
+        let was_on = self.is_tone_on();
+        let tick = self.cpu_timer.execute();
+        self.tick += 1;
+        self.update_tone(was_on);
+        return tick;
+    }
+
+    fn reset(&mut self) {
+        self.set(0);
+        self.pitch = DEFAULT_PITCH;
+        if self.pattern.take().is_some() {
+            self.push_event(ToneEvent::Pattern(self.tick, None));
         }
+    }
+}
 
-        let tick = self.cpu_timer.execute();
+#[cfg(test)]
+mod timers_tests {
+    use super::AudioPattern;
+    use super::SoundTimer;
+    use super::ToneEvent;
+    use crate::threading::Processor;
 
-        if self.cpu_timer.value == 0 {
-            self.beep = false;
+    fn run(timer: &mut SoundTimer, ticks: usize) {
+        for _ in 0..ticks {
+            timer.execute();
         }
-        return tick;
     }
 
-    fn reset(&mut self) {
-        self.cpu_timer.value = 0;
+    #[test]
+    fn given_sound_timer_when_set_should_turn_tone_on_until_zero() {
+        let mut timer = SoundTimer::new(60.0);
+        run(&mut timer, 2);
+
+        timer.set(3);
+        run(&mut timer, 5);
+
+        assert_eq!(timer.take_events(), [ToneEvent::On(2), ToneEvent::Off(5)]);
+    }
+
+    #[test]
+    fn given_running_tone_when_rewritten_should_extend_without_new_event() {
+        let mut timer = SoundTimer::new(60.0);
+
+        timer.set(2);
+        run(&mut timer, 1);
+        timer.set(4);
+        run(&mut timer, 10);
+
+        assert_eq!(timer.take_events(), [ToneEvent::On(0), ToneEvent::Off(5)]);
+    }
+
+    #[test]
+    fn given_running_tone_when_set_to_zero_should_turn_off_at_current_tick() {
+        let mut timer = SoundTimer::new(60.0);
+
+        timer.set(10);
+        run(&mut timer, 3);
+        timer.set(0);
+
+        assert_eq!(timer.take_events(), [ToneEvent::On(0), ToneEvent::Off(3)]);
+        assert!(!timer.is_tone_on());
+    }
+
+    #[test]
+    fn given_one_tick_tone_when_run_should_keep_both_events() {
+        let mut timer = SoundTimer::new(60.0);
+
+        timer.set(1);
+        run(&mut timer, 1);
+        timer.set(1);
+        run(&mut timer, 1);
+
+        assert_eq!(
+            timer.take_events(),
+            [ToneEvent::On(0), ToneEvent::Off(1), ToneEvent::On(1), ToneEvent::Off(2)]
+        );
+        assert_eq!(timer.ticks_to_duration(3).as_millis(), 50);
+    }
+
+    #[test]
+    fn given_pattern_when_pitch_changes_should_report_both_patterns() {
+        let mut timer = SoundTimer::new(60.0);
+        let buffer = [0xF0; 16];
+
+        timer.set_pitch(112);
+        timer.set_pattern(buffer);
+        run(&mut timer, 2);
+        timer.set_pitch(16);
+
+        let high = AudioPattern {
+            buffer: buffer,
+            pitch: 112,
+        };
+        let low = AudioPattern {
+            buffer: buffer,
+            pitch: 16,
+        };
+        assert_eq!(
+            timer.take_events(),
+            [ToneEvent::Pattern(0, Some(high)), ToneEvent::Pattern(2, Some(low))]
+        );
+        assert_eq!(high.rate(), 8000.0);
+        assert_eq!(low.rate(), 2000.0);
+        assert!(high.bit(3));
+        assert!(!high.bit(4));
     }
 }
@@ -0,0 +1,346 @@
+use super::disasm::disassemble;
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::io::BufRead;
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub program_counter: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub stack_pointer: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl TraceEntry {
+    // Fixed width fields, disassembly last so that lines can be diffed as is
+    pub fn format(&self) -> String {
+        let v: Vec<String> = self.v.iter().map(|value| format!("{:02X}", value)).collect();
+        return format!(
+            "PC={:04X} OP={:04X} V={} I={:04X} SP={:02X} DT={:02X} ST={:02X} ; {}",
+            self.program_counter,
+            self.opcode,
+            v.join(":"),
+            self.i,
+            self.stack_pointer,
+            self.delay_timer,
+            self.sound_timer,
+            disassemble(self.opcode)
+        );
+    }
+}
+
+pub trait Tracer {
+    fn trace(&mut self, entry: &TraceEntry);
+
+    // Called when the Cpu halts on a breakpoint, before the instruction at `address` runs
+    fn breakpoint(&mut self, _address: u16) {}
+}
+
+#[cfg(feature = "std")]
+impl<T: Tracer> Tracer for Arc<Mutex<T>> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.lock().unwrap().trace(entry);
+    }
+
+    fn breakpoint(&mut self, address: u16) {
+        self.lock().unwrap().breakpoint(address);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    pub address_range: Option<RangeInclusive<u16>>,
+    pub limit: Option<u64>,
+    pub start_address: Option<u16>,
+    // Starts at the first breakpoint hit instead, with the instruction it stopped on
+    pub start_at_breakpoint: bool,
+}
+
+#[cfg(feature = "std")]
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    filter: TraceFilter,
+    count: u64,
+    started: bool,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W, filter: TraceFilter) -> Self {
+        let started = filter.start_address.is_none() && !filter.start_at_breakpoint;
+        return Self {
+            writer: writer,
+            filter: filter,
+            count: 0,
+            started: started,
+        };
+    }
+
+    pub fn count(&self) -> u64 {
+        return self.count;
+    }
+
+    pub fn get_ref(&self) -> &W {
+        return &self.writer;
+    }
+
+    pub fn into_inner(self) -> W {
+        return self.writer;
+    }
+
+    fn accept(&mut self, entry: &TraceEntry) -> bool {
+        if !self.started {
+            if self.filter.start_at_breakpoint || self.filter.start_address != Some(entry.program_counter) {
+                return false;
+            }
+            self.started = true;
+        }
+
+        if let Some(range) = &self.filter.address_range {
+            if !range.contains(&entry.program_counter) {
+                return false;
+            }
+        }
+
+        return self.filter.limit.is_none_or(|limit| self.count < limit);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if !self.accept(entry) {
+            return;
+        }
+
+        self.count += 1;
+        // A trace that cannot be written is not worth stopping the emulation
+        let _ = writeln!(self.writer, "{}", entry.format());
+    }
+
+    fn breakpoint(&mut self, _address: u16) {
+        self.started |= self.filter.start_at_breakpoint;
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub line: usize,
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub fields: Vec<String>,
+}
+
+#[cfg(feature = "std")]
+pub fn first_divergence<L: BufRead, R: BufRead>(left: L, right: R) -> std::io::Result<Option<Divergence>> {
+    let mut left_lines = left.lines();
+    let mut right_lines = right.lines();
+    let mut line = 0;
+
+    loop {
+        line += 1;
+        let left = left_lines.next().transpose()?;
+        let right = right_lines.next().transpose()?;
+
+        if left.is_none() && right.is_none() {
+            return Ok(None);
+        }
+
+        if left != right {
+            let fields = match (&left, &right) {
+                (Some(left), Some(right)) => differing_fields(left, right),
+                _ => Vec::new(),
+            };
+            return Ok(Some(Divergence {
+                line: line,
+                left: left,
+                right: right,
+                fields: fields,
+            }));
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn differing_fields(left: &str, right: &str) -> Vec<String> {
+    let left = fields(left);
+    let right = fields(right);
+    return left
+        .iter()
+        .zip(right.iter())
+        .filter(|(left, right)| left != right)
+        .map(|(left, _)| left.split('=').next().unwrap().to_string())
+        .collect();
+}
+
+#[cfg(feature = "std")]
+fn fields(line: &str) -> Vec<&str> {
+    let registers = line.split(" ; ").next().unwrap();
+    return registers.split_whitespace().collect();
+}
+
+#[cfg(test)]
+mod trace_tests {
+    #[cfg(feature = "std")]
+    use super::first_divergence;
+    use super::TraceEntry;
+    #[cfg(feature = "std")]
+    use super::TraceFilter;
+    #[cfg(feature = "std")]
+    use super::TraceWriter;
+    #[cfg(feature = "std")]
+    use super::Tracer;
+    #[cfg(feature = "std")]
+    use alloc::format;
+    #[cfg(feature = "std")]
+    use alloc::string::String;
+    #[cfg(feature = "std")]
+    use alloc::vec;
+    #[cfg(feature = "std")]
+    use alloc::vec::Vec;
+
+    fn entry(program_counter: u16) -> TraceEntry {
+        let mut v = [0; 16];
+        v[0] = 0x05;
+        v[0xF] = 0x01;
+        return TraceEntry {
+            program_counter: program_counter,
+            opcode: 0x6105,
+            v: v,
+            i: 0x2A0,
+            stack_pointer: 1,
+            delay_timer: 0x3C,
+            sound_timer: 0,
+        };
+    }
+
+    #[cfg(feature = "std")]
+    fn write(filter: TraceFilter, addresses: &[u16]) -> String {
+        let mut writer = TraceWriter::new(Vec::new(), filter);
+        for address in addresses {
+            writer.trace(&entry(*address));
+        }
+        return String::from_utf8(writer.into_inner()).unwrap();
+    }
+
+    #[test]
+    fn given_entry_when_format_should_write_stable_line() {
+        assert_eq!(
+            entry(0x200).format(),
+            "PC=0200 OP=6105 V=05:00:00:00:00:00:00:00:00:00:00:00:00:00:00:01 I=02A0 SP=01 DT=3C ST=00 ; LD V1, 0x05"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_address_range_when_trace_should_skip_outside_addresses() {
+        let filter = TraceFilter {
+            address_range: Some(0x202..=0x204),
+            ..TraceFilter::default()
+        };
+
+        let trace = write(filter, &[0x200, 0x202, 0x204, 0x206]);
+
+        assert_eq!(trace.lines().count(), 2);
+        assert!(trace.starts_with("PC=0202"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_limit_when_trace_should_stop_after_limit() {
+        let filter = TraceFilter {
+            limit: Some(3),
+            ..TraceFilter::default()
+        };
+
+        let trace = write(filter, &[0x200, 0x202, 0x204, 0x206, 0x208]);
+
+        assert_eq!(trace.lines().count(), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_start_address_when_trace_should_start_at_address() {
+        let filter = TraceFilter {
+            start_address: Some(0x204),
+            ..TraceFilter::default()
+        };
+
+        let trace = write(filter, &[0x200, 0x202, 0x204, 0x202, 0x200]);
+
+        assert_eq!(trace.lines().count(), 3);
+        assert!(trace.starts_with("PC=0204"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_start_at_breakpoint_when_trace_should_start_after_breakpoint() {
+        let filter = TraceFilter {
+            start_at_breakpoint: true,
+            ..TraceFilter::default()
+        };
+        let mut writer = TraceWriter::new(Vec::new(), filter);
+
+        writer.trace(&entry(0x200));
+        writer.trace(&entry(0x202));
+        writer.breakpoint(0x204);
+        writer.trace(&entry(0x204));
+        writer.trace(&entry(0x206));
+
+        let trace = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(trace.lines().count(), 2);
+        assert!(trace.starts_with("PC=0204"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_same_traces_when_first_divergence_should_return_none() {
+        let trace = format!("{}\n{}\n", entry(0x200).format(), entry(0x202).format());
+
+        let divergence = first_divergence(trace.as_bytes(), trace.as_bytes()).unwrap();
+
+        assert_eq!(divergence, None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_different_traces_when_first_divergence_should_report_line_and_fields() {
+        let mut other = entry(0x202);
+        other.v[3] = 0x10;
+        other.i = 0x300;
+        let left = format!("{}\n{}\n", entry(0x200).format(), entry(0x202).format());
+        let right = format!("{}\n{}\n", entry(0x200).format(), other.format());
+
+        let divergence = first_divergence(left.as_bytes(), right.as_bytes()).unwrap().unwrap();
+
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.fields, vec!["V", "I"]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn given_shorter_trace_when_first_divergence_should_report_missing_line() {
+        let left = format!("{}\n", entry(0x200).format());
+        let right = format!("{}\n{}\n", entry(0x200).format(), entry(0x202).format());
+
+        let divergence = first_divergence(left.as_bytes(), right.as_bytes()).unwrap().unwrap();
+
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.left, None);
+    }
+}
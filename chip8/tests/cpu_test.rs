@@ -1,87 +1,45 @@
+#[cfg(feature = "std")]
+use chip8::bus::*;
+use chip8::cheats::*;
 use chip8::constants::*;
 use chip8::cpu::*;
-use chip8::driver::*;
+use chip8::machine::*;
 use chip8::threading::*;
 use chip8::timers::*;
-use std::cell::RefCell;
+#[cfg(feature = "std")]
+use chip8::trace::*;
 use std::fs::read;
 use std::fs::read_to_string;
-use std::rc::Rc;
-use std::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
 use test_case::test_case;
 
-const CPU_FREQ: f64 = 500.0;
-const TIMER_FREQ: f64 = 60.0;
-
-struct FakeDriver {}
-
-impl FakeDriver {
-    fn new() -> Self {
-        return Self {};
-    }
-}
-
-impl Driver for FakeDriver {
-    fn sound_do_beep(&mut self, _frequency: u32, _duration: u32) {}
-
-    fn video_fill_buffer(&mut self, _display: &Vec<Vec<usize>>) {}
-
-    fn input_is_key_down(&mut self, _key: u8) -> bool {
-        return false;
-    }
-
-    fn input_is_key_up(&mut self, _key: u8) -> bool {
-        return true;
-    }
-
-    fn input_is_any_key_down(&mut self, _key: &mut u8) -> bool {
-        return false;
-    }
-}
-
 #[test_case("../test_roms/c8_test.c8" ,"../test_roms/c8_test.json" ; "c8_test")]
 #[test_case("../test_roms/test_opcode.ch8" ,"../test_roms/test_opcode.json" ; "test_opcode")]
 fn given_test_rom_when_tick_should_wrk(rom_path: &str, buffer_path: &str) {
-    let driver = Rc::new(RefCell::new(FakeDriver::new()));
-    let delay_timer = Rc::new(RefCell::new(CpuTimer::new()));
-    let sound_timer = Rc::new(RefCell::new(SoundTimer::new(TIMER_FREQ, driver.clone())));
-    let cpu = Rc::new(RefCell::new(Cpu::new(
-        delay_timer.clone(),
-        sound_timer.clone(),
-        driver.clone(),
-    )));
+    let mut machine = Machine::new();
 
     let rom: Vec<u8> = read(rom_path).unwrap();
-    cpu.borrow_mut().load(rom);
-
-    let cpu_frequency = Frequency::new(CPU_FREQ, 1.0);
-    let timer_frequency = cpu_frequency.get_sub_frequency(TIMER_FREQ, 1.0);
-    let mut runner = ThreadRunner::new(
-        cpu_frequency,
-        vec![
-            Thread::new(cpu_frequency, cpu.clone()),
-            Thread::new(timer_frequency, delay_timer),
-            Thread::new(timer_frequency, sound_timer),
-        ],
-    );
-    runner.reset();
+    machine.load_rom(rom);
 
     let buffer_content = read_to_string(buffer_path).unwrap();
     let buffer_data: Vec<usize> = serde_json::from_str(&buffer_content).unwrap();
 
-    runner.tick(Duration::from_secs(1));
+    machine.run_for_nanos(1_000_000_000);
 
     let mut index = 0;
     let mut test_ok = true;
     for y in 0..Y_SIZE {
         for x in 0..X_SIZE {
-            if cpu.borrow().display[x][y] == 1 {
+            if machine.framebuffer().get(0, x, y) {
                 print!("+",);
             } else {
                 print!(" ",);
             }
 
-            if cpu.borrow().display[x][y] != buffer_data[index] {
+            if machine.framebuffer().pixel(x, y) as usize != buffer_data[index] {
                 test_ok = false;
             }
             index += 1;
@@ -91,3 +49,277 @@ fn given_test_rom_when_tick_should_wrk(rom_path: &str, buffer_path: &str) {
 
     assert_eq!(test_ok, true);
 }
+
+fn new_cpu(rom: Vec<u8>) -> Cpu {
+    let mut cpu = Cpu::new(TIMER_FREQUENCY);
+    cpu.load(rom);
+    return cpu;
+}
+
+#[test]
+fn given_breakpoint_when_execute_should_halt_before_instruction() {
+    let mut cpu = new_cpu(vec![0x60, 0x05, 0x61, 0x07, 0x12, 0x04]);
+    cpu.add_breakpoint(0x202);
+
+    for _i in 0..4 {
+        cpu.execute();
+    }
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.program_counter(), 0x202);
+    assert_eq!(cpu.v(0), 0x05);
+    assert_eq!(cpu.v(1), 0x00);
+}
+
+#[test]
+fn given_halted_on_breakpoint_when_resume_should_execute_instruction() {
+    let mut cpu = new_cpu(vec![0x60, 0x05, 0x61, 0x07, 0x12, 0x04]);
+    cpu.add_breakpoint(0x202);
+    cpu.execute();
+    cpu.execute();
+
+    cpu.resume();
+    cpu.execute();
+
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.program_counter(), 0x204);
+    assert_eq!(cpu.v(1), 0x07);
+}
+
+#[test]
+fn given_halted_cpu_when_step_should_execute_one_instruction() {
+    let mut cpu = new_cpu(vec![0x60, 0x05, 0x22, 0x06, 0x00, 0x00, 0x00, 0xEE]);
+    cpu.halt();
+
+    cpu.step();
+    cpu.step();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.v(0), 0x05);
+    assert_eq!(cpu.program_counter(), 0x206);
+    assert_eq!(cpu.stack(), &[0x204]);
+}
+
+#[test]
+fn given_address_when_write_memory_should_read_back() {
+    let mut cpu = new_cpu(Vec::new());
+
+    assert!(cpu.write_memory(0x300, 0xAB));
+    assert!(!cpu.write_memory(cpu.memory_size(), 0xAB));
+
+    assert_eq!(cpu.read_memory(0x300), Some(0xAB));
+    assert_eq!(cpu.read_memory(cpu.memory_size()), None);
+}
+
+#[test]
+fn given_watchpoint_when_memory_changes_should_halt_with_values() {
+    let mut cpu = new_cpu(vec![0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06]);
+    cpu.add_watchpoint(0x300);
+
+    for _i in 0..4 {
+        cpu.execute();
+    }
+
+    assert_eq!(
+        cpu.halt_reason(),
+        Some(HaltReason::Watchpoint {
+            address: 0x300,
+            old: 0x00,
+            new: 0x2A
+        })
+    );
+    assert_eq!(cpu.program_counter(), 0x206);
+}
+
+// Tracers and observers shared through Arc<Mutex> need std
+#[cfg(feature = "std")]
+#[test]
+fn given_tracer_when_execute_should_trace_state_before_instruction() {
+    let mut cpu = new_cpu(vec![0x60, 0x05, 0x61, 0x07, 0x12, 0x04]);
+    let tracer = Arc::new(Mutex::new(TraceWriter::new(Vec::new(), TraceFilter::default())));
+    cpu.attach_tracer(Box::new(tracer.clone()));
+
+    for _i in 0..3 {
+        cpu.execute();
+    }
+
+    let trace = String::from_utf8(tracer.lock().unwrap().get_ref().clone()).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("PC=0200 OP=6005 V=00:"));
+    assert!(lines[1].starts_with("PC=0202 OP=6107 V=05:00:"));
+    assert!(lines[2].ends_with("; JP 0x204"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn given_trace_from_breakpoint_when_breakpoint_hit_should_start_tracing_there() {
+    let mut cpu = new_cpu(vec![0x60, 0x05, 0x61, 0x07, 0x62, 0x09, 0x12, 0x06]);
+    let filter = TraceFilter {
+        start_at_breakpoint: true,
+        ..TraceFilter::default()
+    };
+    let tracer = Arc::new(Mutex::new(TraceWriter::new(Vec::new(), filter)));
+    cpu.attach_tracer(Box::new(tracer.clone()));
+    cpu.add_breakpoint(0x204);
+
+    for _i in 0..3 {
+        cpu.execute();
+    }
+    assert_eq!(cpu.halt_reason(), Some(HaltReason::Breakpoint(0x204)));
+    cpu.resume();
+    for _i in 0..2 {
+        cpu.execute();
+    }
+
+    let trace = String::from_utf8(tracer.lock().unwrap().get_ref().clone()).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("PC=0204 OP=6209 V=05:07:"));
+}
+
+#[cfg(feature = "std")]
+struct DataWrites {
+    writes: Vec<(u16, u8)>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryObserver for DataWrites {
+    fn on_write(&mut self, address: u16, value: u8, access: Access) {
+        if access == Access::Data {
+            self.writes.push((address, value));
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn given_memory_observer_when_store_registers_should_observe_writes() {
+    let mut cpu = new_cpu(vec![0x60, 0x2A, 0x61, 0x07, 0xA3, 0x00, 0xF1, 0x55]);
+    let observer = Arc::new(Mutex::new(DataWrites { writes: Vec::new() }));
+    cpu.attach_memory_observer(Box::new(observer.clone()));
+
+    for _i in 0..4 {
+        cpu.execute();
+    }
+
+    assert_eq!(observer.lock().unwrap().writes, vec![(0x300, 0x2A), (0x301, 0x07)]);
+}
+
+#[test]
+fn given_enabled_cheats_when_apply_should_freeze_every_time_and_poke_once() {
+    let mut cpu = new_cpu(Vec::new());
+    let mut engine = CheatEngine::parse("[Lives]\nfreeze 0x300 0x09\n[Level]\npoke v5 0x03").unwrap();
+    engine.set_enabled(0, true);
+    engine.set_enabled(1, true);
+
+    engine.apply(&mut cpu);
+    cpu.write_memory(0x300, 0x01);
+    cpu.set_v(5, 0x07);
+    engine.apply(&mut cpu);
+
+    assert_eq!(cpu.read_memory(0x300), Some(0x09));
+    assert_eq!(cpu.v(5), 0x07);
+}
+
+#[test]
+fn given_snapshots_when_search_should_narrow_candidates() {
+    let mut cpu = new_cpu(Vec::new());
+    cpu.write_memory(0x300, 3);
+    cpu.write_memory(0x301, 3);
+    let mut search = MemorySearch::new(&cpu);
+
+    search.filter(&cpu, SearchCondition::Equal(3));
+    cpu.write_memory(0x300, 2);
+    search.filter(&cpu, SearchCondition::Decreased);
+
+    assert_eq!(search.candidates(), &[0x300]);
+}
+
+#[test]
+fn given_64_kb_memory_when_search_should_cover_every_address() {
+    let layout = MemoryLayout {
+        memory_size: 0x10000,
+        ..MemoryLayout::default()
+    };
+    let mut cpu = Cpu::with_layout(TIMER_FREQUENCY, layout, FONTS.to_vec());
+    cpu.write_memory(0xFFFF, 7);
+
+    let mut search = MemorySearch::new(&cpu);
+    assert_eq!(search.candidates().len(), 0x10000);
+    search.filter(&cpu, SearchCondition::Equal(7));
+
+    assert_eq!(search.candidates(), &[0xFFFF]);
+}
+
+#[test]
+fn given_vip_quirks_when_shift_and_store_should_use_vy_and_increment_i() {
+    let mut cpu = new_cpu(vec![0x61, 0x06, 0x80, 0x16, 0xA3, 0x00, 0xF1, 0x55]);
+    cpu.set_quirks(Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        ..Quirks::default()
+    });
+
+    for _i in 0..4 {
+        cpu.execute();
+    }
+
+    assert_eq!(cpu.v(0), 0x03);
+    assert_eq!(cpu.i(), 0x302);
+}
+
+#[test]
+fn given_xo_chip_audio_when_execute_should_load_pattern_and_pitch() {
+    // LD I, 0x208 ; AUDIO ; LD V3, 0x70 ; PITCH V3 ; pattern
+    let mut rom = vec![0xA2, 0x08, 0xF0, 0x02, 0x63, 0x70, 0xF3, 0x3A];
+    rom.extend_from_slice(&[0xAA; 16]);
+    let mut cpu = new_cpu(rom);
+
+    for _i in 0..4 {
+        cpu.execute();
+    }
+
+    let events = cpu.take_tone_events();
+    let pattern = AudioPattern {
+        buffer: [0xAA; 16],
+        pitch: 0x70,
+    };
+    assert_eq!(events.last(), Some(&ToneEvent::Pattern(0, Some(pattern))));
+    assert_eq!(pattern.rate(), 8000.0);
+}
+
+#[test]
+fn given_out_of_range_values_when_set_pc_or_sp_should_refuse_them() {
+    let mut cpu = new_cpu(vec![0x12, 0x00]);
+
+    assert!(cpu.set_program_counter(0xFFE).is_ok());
+    assert!(cpu.set_program_counter(0xFFF).is_err());
+    assert!(cpu.set_stack_pointer(16).is_ok());
+    assert!(cpu.set_stack_pointer(17).is_err());
+
+    assert_eq!(cpu.program_counter(), 0xFFE);
+    assert_eq!(cpu.stack().len(), 16);
+}
+
+#[test]
+fn given_watchpoint_when_step_should_halt_on_write() {
+    // LD V0, 0x2A ; LD I, 0x300 ; LD [I], V0
+    let mut cpu = new_cpu(vec![0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55]);
+    cpu.add_watchpoint(0x300);
+    cpu.halt();
+
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.halt_reason(), Some(HaltReason::Request));
+    cpu.step();
+
+    assert_eq!(
+        cpu.halt_reason(),
+        Some(HaltReason::Watchpoint {
+            address: 0x300,
+            old: 0x00,
+            new: 0x2A
+        })
+    );
+}
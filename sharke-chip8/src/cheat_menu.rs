@@ -0,0 +1,179 @@
+use chip8::cheats::CheatEngine;
+use chip8::cpu::Cpu;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
+
+const CHEATS_DIRECTORY: &str = "cheats";
+const CHEATS_EXTENSION: &str = "cht";
+
+pub struct CheatMenu {
+    engine: CheatEngine,
+    open: bool,
+    selected: usize,
+}
+
+impl CheatMenu {
+    pub fn new(engine: CheatEngine) -> Self {
+        return Self {
+            engine: engine,
+            open: false,
+            selected: 0,
+        };
+    }
+
+    // Cheats are looked up in "<dir>/<sha1 of the rom>.cht", "<dir>" defaulting to "cheats" next to the rom
+    pub fn load(rom_path: &Path, rom: &[u8], directory: Option<&Path>) -> Self {
+        let path = cheats_path(rom_path, rom, directory);
+        let engine = match read_to_string(&path) {
+            Ok(text) => match CheatEngine::parse(&text) {
+                Ok(engine) => {
+                    println!("'{}' loaded, {} cheats", path.display(), engine.cheats().len());
+                    engine
+                }
+                Err(error) => {
+                    println!("'{}' ignored ({})", path.display(), error);
+                    CheatEngine::new()
+                }
+            },
+            Err(_) => CheatEngine::new(),
+        };
+        return Self::new(engine);
+    }
+
+    pub fn is_open(&self) -> bool {
+        return self.open;
+    }
+
+    pub fn apply(&mut self, cpu: &mut Cpu) {
+        self.engine.apply(cpu);
+    }
+
+    // Returns true when the event was consumed by the menu, only key presses are
+    // consumed so that keys held when it opens are still released
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        let keycode = match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => *keycode,
+            _ => return false,
+        };
+
+        if keycode == Keycode::Tab {
+            self.open = !self.open && !self.engine.cheats().is_empty();
+            return true;
+        }
+
+        if !self.open {
+            return false;
+        }
+
+        let count = self.engine.cheats().len();
+        match keycode {
+            Keycode::Up => self.selected = (self.selected + count - 1) % count,
+            Keycode::Down => self.selected = (self.selected + 1) % count,
+            Keycode::Return | Keycode::Space => self.engine.toggle(self.selected),
+            _ => {}
+        }
+        return true;
+    }
+
+    pub fn title(&self) -> Option<String> {
+        if !self.open {
+            return None;
+        }
+
+        let cheat = &self.engine.cheats()[self.selected];
+        return Some(format!(
+            "Cheats [{}/{}] {}: {} (Up/Down select, Enter toggle, Tab close)",
+            self.selected + 1,
+            self.engine.cheats().len(),
+            cheat.name,
+            if cheat.is_enabled() { "ON" } else { "OFF" }
+        ));
+    }
+}
+
+fn cheats_path(rom_path: &Path, rom: &[u8], directory: Option<&Path>) -> PathBuf {
+    let directory = match directory {
+        Some(directory) => directory.to_path_buf(),
+        None => rom_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(CHEATS_DIRECTORY),
+    };
+    let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+    return directory.join(hash).with_extension(CHEATS_EXTENSION);
+}
+
+#[cfg(test)]
+mod cheat_menu_tests {
+    use super::CheatMenu;
+    use chip8::cheats::CheatEngine;
+    use sdl2::event::Event;
+    use sdl2::keyboard::Keycode;
+    use sdl2::keyboard::Mod;
+
+    fn menu() -> CheatMenu {
+        return CheatMenu::new(CheatEngine::parse("[Lives]\nfreeze 0x2F1 0x03").unwrap());
+    }
+
+    fn key_down(keycode: Keycode) -> Event {
+        return Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        };
+    }
+
+    fn key_up(keycode: Keycode) -> Event {
+        return Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        };
+    }
+
+    #[test]
+    fn given_open_menu_when_key_down_should_consume_it() {
+        let mut menu = menu();
+
+        assert!(menu.handle_event(&key_down(Keycode::Tab)));
+        assert!(menu.is_open());
+        assert!(menu.handle_event(&key_down(Keycode::P)));
+        assert!(menu.handle_event(&key_down(Keycode::Q)));
+    }
+
+    #[test]
+    fn given_open_menu_when_key_up_should_let_it_through() {
+        let mut menu = menu();
+        menu.handle_event(&key_down(Keycode::Tab));
+
+        assert!(!menu.handle_event(&key_up(Keycode::Q)));
+    }
+
+    #[test]
+    fn given_closed_menu_when_key_down_should_let_it_through() {
+        let mut menu = menu();
+
+        assert!(!menu.handle_event(&key_down(Keycode::P)));
+    }
+
+    #[test]
+    fn given_no_cheats_when_tab_should_stay_closed() {
+        let mut menu = CheatMenu::new(CheatEngine::new());
+
+        menu.handle_event(&key_down(Keycode::Tab));
+
+        assert!(!menu.is_open());
+    }
+}
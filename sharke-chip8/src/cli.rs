@@ -0,0 +1,619 @@
+use chip8::audio::Waveform;
+use chip8::builder::Variant;
+use chip8::cpu::MemoryLayout;
+use chip8::cpu::PresentMode;
+use chip8::cpu::Quirks;
+use chip8::threading::Speed;
+use chip8::trace::TraceFilter;
+use chip8::Chip8Builder;
+use crate::controller::ControllerMap;
+use crate::keymap::KeyMap;
+use crate::palette::THEMES;
+use crate::sdl2_drivers::AntiFlicker;
+use std::fmt;
+use std::str::FromStr;
+
+pub const DEFAULT_VOLUME: u8 = 25;
+pub const DEFAULT_TEST_FRAMES: u64 = 600;
+pub const DEFAULT_BENCH_SECONDS: u64 = 60;
+
+pub const USAGE: &str = "\
+usage: sharke-chip8 [run] <rom> [options]
+       sharke-chip8 disasm <rom> [--origin <address>]
+       sharke-chip8 asm <source> [-o <rom>]
+       sharke-chip8 info <rom>
+       sharke-chip8 bench <rom> [--seconds <n>] [--audio-out <wav>] [machine options]
+       sharke-chip8 test <rom> [--frames <n>] [--expect <sha1>] [--audio-out <wav>] [machine options]
+       sharke-chip8 trace-diff <left> <right>
+
+machine options:
+  --variant <chip8|schip|xochip>   interpreter variant, also selects its quirks
+  --quirks <list>                  comma separated quirks replacing the variant ones:
+                                   shift,load-store,jump,logic,wrap or none
+  --cpu-freq <hz>                  cpu frequency (default 500)
+  --timer-freq <hz>                timer and frame frequency (default 60)
+  --ipf <n>                        fixed instructions per frame instead of the cpu frequency
+
+run options:
+  --scale <n>                      window size in pixels per chip-8 pixel
+  --fullscreen, --no-fullscreen    desktop fullscreen window or not, whatever the config says
+  --theme <name>                   color theme: default, green, amber, octo, lcd, high-contrast
+  --fg <rrggbb>, --bg <rrggbb>     pixel and background colors, replacing the theme ones
+  --fg2 <rrggbb>, --blend <rrggbb> XO-CHIP plane 2 and both planes colors
+  --keymap <keys>                  comma separated layouts (qwerty, azerty, numpad, arrows) and
+                                   <hex key>=<host key> bindings, or 16 host keys for keys 0 to F
+  --speed <multiplier|unlimited>   emulation speed (default 1)
+  --config <file>                  configuration file instead of the user one
+  --controller <profiles>          comma separated controller profiles (default, pong, tetris,
+                                   breakout) and <hex key>=<input> bindings
+  --mute, --no-mute                no audio device, --audio-out still records
+  --waveform <square|triangle|sine>, --pitch <hz>, --volume <0-100>, --audio-out <wav>
+  --immediate                      present after every CLS/DRW
+  --anti-flicker <mode>            off, phosphor[:<frames>], blend or non-erasing
+  --debug, --debug-script <file>, --gdb <port>
+  --trace <file>, --trace-range <from-to>, --trace-limit <n>, --trace-from <address|break>
+  --profile <file>, --profile-folded <file>, --cheats <directory>";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.0);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MachineOptions {
+    pub variant: Option<Variant>,
+    pub quirks: Option<Quirks>,
+    pub cpu_frequency: Option<f64>,
+    pub timer_frequency: Option<f64>,
+    pub instructions_per_frame: Option<u32>,
+}
+
+impl MachineOptions {
+    pub fn builder(&self) -> Chip8Builder {
+        let mut builder = Chip8Builder::new();
+        if let Some(variant) = self.variant {
+            builder = builder.variant(variant);
+        }
+        if let Some(quirks) = self.quirks {
+            builder = builder.quirks(quirks);
+        }
+        if let Some(frequency) = self.cpu_frequency {
+            builder = builder.cpu_frequency(frequency);
+        }
+        if let Some(frequency) = self.timer_frequency {
+            builder = builder.timer_frequency(frequency);
+        }
+        if let Some(instructions) = self.instructions_per_frame {
+            builder = builder.instructions_per_frame(instructions);
+        }
+        return builder;
+    }
+}
+
+pub struct RunOptions {
+    pub rom_path: String,
+    pub machine: MachineOptions,
+    pub gdb_port: Option<u16>,
+    pub debug: bool,
+    pub debug_script: Option<String>,
+    pub trace_path: Option<String>,
+    pub trace_filter: TraceFilter,
+    pub profile_path: Option<String>,
+    pub profile_folded_path: Option<String>,
+    pub cheats_directory: Option<String>,
+    pub present_mode: PresentMode,
+    pub anti_flicker: Option<AntiFlicker>,
+    pub scale: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub theme: Option<String>,
+    pub foreground: Option<[u8; 4]>,
+    pub background: Option<[u8; 4]>,
+    pub foreground2: Option<[u8; 4]>,
+    pub blend: Option<[u8; 4]>,
+    pub key_map: Option<KeyMap>,
+    pub controller_map: Option<ControllerMap>,
+    pub mute: Option<bool>,
+    pub waveform: Option<Waveform>,
+    pub pitch: Option<u32>,
+    pub volume: Option<u8>,
+    pub audio_out: Option<String>,
+    pub speed: Option<Speed>,
+    pub config_path: Option<String>,
+}
+
+impl RunOptions {
+    fn new(rom_path: String) -> Self {
+        return Self {
+            rom_path: rom_path,
+            machine: MachineOptions::default(),
+            gdb_port: None,
+            debug: false,
+            debug_script: None,
+            trace_path: None,
+            trace_filter: TraceFilter::default(),
+            profile_path: None,
+            profile_folded_path: None,
+            cheats_directory: None,
+            present_mode: PresentMode::Frame,
+            anti_flicker: None,
+            scale: None,
+            fullscreen: None,
+            theme: None,
+            foreground: None,
+            background: None,
+            foreground2: None,
+            blend: None,
+            key_map: None,
+            controller_map: None,
+            mute: None,
+            waveform: None,
+            pitch: None,
+            volume: None,
+            audio_out: None,
+            speed: None,
+            config_path: None,
+        };
+    }
+}
+
+pub enum Command {
+    Help,
+    Run(Box<RunOptions>),
+    Disasm { rom_path: String, origin: u16 },
+    Asm { source_path: String, output_path: Option<String> },
+    Info { rom_path: String },
+    Bench {
+        rom_path: String,
+        seconds: u64,
+        audio_out: Option<String>,
+        machine: MachineOptions,
+    },
+    Test {
+        rom_path: String,
+        frames: u64,
+        expect: Option<String>,
+        audio_out: Option<String>,
+        machine: MachineOptions,
+    },
+    TraceDiff { left_path: String, right_path: String },
+}
+
+// Arguments without the program name, a rom path without subcommand is a "run"
+pub fn parse(args: &[String]) -> Result<Command, CliError> {
+    let Some(first) = args.first() else {
+        return Err(CliError(String::from("missing rom path")));
+    };
+    let mut arguments = Arguments { args: args, index: 1 };
+    return match first.as_str() {
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        "run" => parse_run(&mut arguments),
+        "disasm" => {
+            let rom_path = arguments.positional("rom path")?;
+            let mut origin = MemoryLayout::default().program_start as u16;
+            while let Some(flag) = arguments.next() {
+                match flag {
+                    "--origin" => origin = parse_address(&arguments.value(flag)?)?,
+                    _ => return Err(unknown_option(flag)),
+                }
+            }
+            Ok(Command::Disasm {
+                rom_path: rom_path,
+                origin: origin,
+            })
+        }
+        "asm" => {
+            let source_path = arguments.positional("source path")?;
+            let mut output_path = None;
+            while let Some(flag) = arguments.next() {
+                match flag {
+                    "-o" | "--output" => output_path = Some(arguments.value(flag)?),
+                    _ => return Err(unknown_option(flag)),
+                }
+            }
+            Ok(Command::Asm {
+                source_path: source_path,
+                output_path: output_path,
+            })
+        }
+        "info" => {
+            let rom_path = arguments.positional("rom path")?;
+            arguments.end()?;
+            Ok(Command::Info { rom_path: rom_path })
+        }
+        "bench" => {
+            let rom_path = arguments.positional("rom path")?;
+            let mut seconds = DEFAULT_BENCH_SECONDS;
+            let mut audio_out = None;
+            let mut machine = MachineOptions::default();
+            while let Some(flag) = arguments.next() {
+                match flag {
+                    "--seconds" => seconds = arguments.parsed(flag)?,
+                    "--audio-out" => audio_out = Some(arguments.value(flag)?),
+                    _ => parse_machine_option(flag, &mut arguments, &mut machine)?,
+                }
+            }
+            Ok(Command::Bench {
+                rom_path: rom_path,
+                seconds: seconds,
+                audio_out: audio_out,
+                machine: machine,
+            })
+        }
+        "test" => {
+            let rom_path = arguments.positional("rom path")?;
+            let mut frames = DEFAULT_TEST_FRAMES;
+            let mut expect = None;
+            let mut audio_out = None;
+            let mut machine = MachineOptions::default();
+            while let Some(flag) = arguments.next() {
+                match flag {
+                    "--frames" => frames = arguments.parsed(flag)?,
+                    "--expect" => expect = Some(arguments.value(flag)?.to_ascii_lowercase()),
+                    "--audio-out" => audio_out = Some(arguments.value(flag)?),
+                    _ => parse_machine_option(flag, &mut arguments, &mut machine)?,
+                }
+            }
+            Ok(Command::Test {
+                rom_path: rom_path,
+                frames: frames,
+                expect: expect,
+                audio_out: audio_out,
+                machine: machine,
+            })
+        }
+        "trace-diff" => {
+            let left_path = arguments.positional("left trace path")?;
+            let right_path = arguments.positional("right trace path")?;
+            arguments.end()?;
+            Ok(Command::TraceDiff {
+                left_path: left_path,
+                right_path: right_path,
+            })
+        }
+        _ => {
+            arguments.index = 0;
+            parse_run(&mut arguments)
+        }
+    };
+}
+
+struct Arguments<'a> {
+    args: &'a [String],
+    index: usize,
+}
+
+impl<'a> Arguments<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.index)?;
+        self.index += 1;
+        return Some(arg.as_str());
+    }
+
+    fn positional(&mut self, name: &str) -> Result<String, CliError> {
+        return match self.next() {
+            Some(arg) if !arg.starts_with('-') => Ok(String::from(arg)),
+            _ => Err(CliError(format!("missing {}", name))),
+        };
+    }
+
+    fn value(&mut self, flag: &str) -> Result<String, CliError> {
+        return self
+            .next()
+            .map(String::from)
+            .ok_or_else(|| CliError(format!("{} expects a value", flag)));
+    }
+
+    fn parsed<T: FromStr>(&mut self, flag: &str) -> Result<T, CliError> {
+        let value = self.value(flag)?;
+        return value
+            .parse::<T>()
+            .map_err(|_| CliError(format!("invalid value '{}' for {}", value, flag)));
+    }
+
+    fn end(&mut self) -> Result<(), CliError> {
+        return match self.next() {
+            Some(arg) => Err(unknown_option(arg)),
+            None => Ok(()),
+        };
+    }
+}
+
+fn unknown_option(flag: &str) -> CliError {
+    return CliError(format!("unknown option '{}'", flag));
+}
+
+fn parse_run(arguments: &mut Arguments) -> Result<Command, CliError> {
+    let mut rom_path = None;
+    let mut options = RunOptions::new(String::new());
+
+    while let Some(flag) = arguments.next() {
+        match flag {
+            "--gdb" => options.gdb_port = Some(arguments.parsed(flag)?),
+            "--debug" => options.debug = true,
+            "--debug-script" => {
+                options.debug = true;
+                options.debug_script = Some(arguments.value(flag)?);
+            }
+            "--trace" => options.trace_path = Some(arguments.value(flag)?),
+            "--trace-range" => {
+                let range = arguments.value(flag)?;
+                let (start, end) = range
+                    .split_once('-')
+                    .ok_or_else(|| CliError(format!("invalid range '{}', expected <from>-<to>", range)))?;
+                options.trace_filter.address_range = Some(parse_address(start)?..=parse_address(end)?);
+            }
+            "--trace-limit" => options.trace_filter.limit = Some(arguments.parsed(flag)?),
+            "--trace-from" => match arguments.value(flag)?.as_str() {
+                "break" => options.trace_filter.start_at_breakpoint = true,
+                address => options.trace_filter.start_address = Some(parse_address(address)?),
+            },
+            "--profile" => options.profile_path = Some(arguments.value(flag)?),
+            "--profile-folded" => options.profile_folded_path = Some(arguments.value(flag)?),
+            "--cheats" => options.cheats_directory = Some(arguments.value(flag)?),
+            "--immediate" => options.present_mode = PresentMode::Immediate,
+            "--anti-flicker" => options.anti_flicker = Some(parse_anti_flicker(&arguments.value(flag)?)?),
+            "--scale" => {
+                let scale: u32 = arguments.parsed(flag)?;
+                if scale == 0 {
+                    return Err(CliError(String::from("--scale must be at least 1")));
+                }
+                options.scale = Some(scale);
+            }
+            "--fullscreen" => options.fullscreen = Some(true),
+            "--no-fullscreen" => options.fullscreen = Some(false),
+            "--fg" => options.foreground = Some(parse_color(&arguments.value(flag)?)?),
+            "--bg" => options.background = Some(parse_color(&arguments.value(flag)?)?),
+            "--fg2" => options.foreground2 = Some(parse_color(&arguments.value(flag)?)?),
+            "--blend" => options.blend = Some(parse_color(&arguments.value(flag)?)?),
+            "--theme" => options.theme = Some(parse_theme(&arguments.value(flag)?)?),
+            "--keymap" => options.key_map = Some(parse_key_map(&arguments.value(flag)?)?),
+            "--controller" => options.controller_map = Some(parse_controller_map(&arguments.value(flag)?)?),
+            "--mute" => options.mute = Some(true),
+            "--no-mute" => options.mute = Some(false),
+            "--waveform" => options.waveform = Some(parse_waveform(&arguments.value(flag)?)?),
+            "--pitch" => options.pitch = Some(arguments.parsed(flag)?),
+            "--volume" => options.volume = Some(arguments.parsed::<u8>(flag)?.min(100)),
+            "--speed" => options.speed = Some(parse_speed(&arguments.value(flag)?)?),
+            "--config" => options.config_path = Some(arguments.value(flag)?),
+            "--audio-out" => options.audio_out = Some(arguments.value(flag)?),
+            _ if flag.starts_with('-') => parse_machine_option(flag, arguments, &mut options.machine)?,
+            _ if rom_path.is_none() => rom_path = Some(String::from(flag)),
+            _ => return Err(CliError(format!("unexpected argument '{}'", flag))),
+        }
+    }
+
+    options.rom_path = rom_path.ok_or_else(|| CliError(String::from("missing rom path")))?;
+    return Ok(Command::Run(Box::new(options)));
+}
+
+fn parse_machine_option(flag: &str, arguments: &mut Arguments, machine: &mut MachineOptions) -> Result<(), CliError> {
+    match flag {
+        "--variant" => machine.variant = Some(parse_variant(&arguments.value(flag)?)?),
+        "--quirks" => machine.quirks = Some(parse_quirks(&arguments.value(flag)?)?),
+        "--cpu-freq" => machine.cpu_frequency = Some(arguments.parsed(flag)?),
+        "--timer-freq" => machine.timer_frequency = Some(arguments.parsed(flag)?),
+        "--ipf" => machine.instructions_per_frame = Some(arguments.parsed(flag)?),
+        _ => return Err(unknown_option(flag)),
+    }
+    return Ok(());
+}
+
+pub fn parse_address(text: &str) -> Result<u16, CliError> {
+    let address = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse::<u16>().ok(),
+    };
+    return address.ok_or_else(|| CliError(format!("invalid address '{}'", text)));
+}
+
+pub fn parse_waveform(text: &str) -> Result<Waveform, CliError> {
+    return Waveform::parse(text).ok_or_else(|| CliError(format!("unknown waveform '{}'", text)));
+}
+
+// Multiplier of the emulated time, or "unlimited"
+pub fn parse_speed(text: &str) -> Result<Speed, CliError> {
+    if text == "unlimited" {
+        return Ok(Speed::Unlimited);
+    }
+    return text
+        .parse::<f64>()
+        .ok()
+        .filter(|multiplier| multiplier.is_finite() && *multiplier > 0.0)
+        .map(Speed::Multiplier)
+        .ok_or_else(|| CliError(format!("invalid speed '{}'", text)));
+}
+
+pub fn parse_variant(text: &str) -> Result<Variant, CliError> {
+    return match text {
+        "chip8" => Ok(Variant::Chip8),
+        "schip" => Ok(Variant::SuperChip),
+        "xochip" => Ok(Variant::XoChip),
+        _ => Err(CliError(format!("unknown variant '{}'", text))),
+    };
+}
+
+pub fn parse_quirks(text: &str) -> Result<Quirks, CliError> {
+    let mut quirks = Quirks::default();
+    if text == "none" {
+        return Ok(quirks);
+    }
+    for name in text.split(',') {
+        match name.trim() {
+            "shift" => quirks.shift_uses_vy = true,
+            "load-store" => quirks.load_store_increments_i = true,
+            "jump" => quirks.jump_uses_vx = true,
+            "logic" => quirks.logic_resets_vf = true,
+            "wrap" => quirks.wrap_sprites = true,
+            _ => return Err(CliError(format!("unknown quirk '{}'", name))),
+        }
+    }
+    return Ok(quirks);
+}
+
+// "rrggbb", with or without a leading '#'
+pub fn parse_color(text: &str) -> Result<[u8; 4], CliError> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6 && hex.chars().all(|digit| digit.is_ascii_hexdigit()))
+        .ok_or_else(|| CliError(format!("invalid color '{}', expected rrggbb", text)))?;
+    let [_, red, green, blue] = value.to_be_bytes();
+    return Ok([red, green, blue, 0xFF]);
+}
+
+pub fn parse_theme(text: &str) -> Result<String, CliError> {
+    if !THEMES.contains(&text) {
+        return Err(CliError(format!("unknown theme '{}', expected one of {}", text, THEMES.join(", "))));
+    }
+    return Ok(text.to_string());
+}
+
+pub fn parse_anti_flicker(text: &str) -> Result<AntiFlicker, CliError> {
+    return AntiFlicker::parse(text).ok_or_else(|| {
+        CliError(format!("unknown anti-flicker mode '{}', expected off, phosphor[:<frames>], blend or non-erasing", text))
+    });
+}
+
+pub fn parse_key_map(text: &str) -> Result<KeyMap, CliError> {
+    return KeyMap::parse(text).map_err(CliError);
+}
+
+pub fn parse_controller_map(text: &str) -> Result<ControllerMap, CliError> {
+    return ControllerMap::parse(text).map_err(CliError);
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn parse_args(args: &[&str]) -> Result<Command, CliError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return parse(&args);
+    }
+
+    fn run_options(args: &[&str]) -> RunOptions {
+        return match parse_args(args) {
+            Ok(Command::Run(options)) => *options,
+            _ => panic!("not a run command"),
+        };
+    }
+
+    fn error(args: &[&str]) -> String {
+        return match parse_args(args) {
+            Err(CliError(message)) => message,
+            Ok(_) => panic!("{:?} should not parse", args),
+        };
+    }
+
+    #[test]
+    fn given_rom_path_without_subcommand_should_run_it() {
+        let options = run_options(&["pong.ch8", "--scale", "10", "--mute", "--variant", "schip"]);
+
+        assert_eq!(options.rom_path, "pong.ch8");
+        assert_eq!(options.scale, Some(10));
+        assert_eq!(options.mute, Some(true));
+        assert_eq!(options.machine.variant, Some(Variant::SuperChip));
+        assert_eq!(run_options(&["run", "pong.ch8"]).rom_path, "pong.ch8");
+    }
+
+    #[test]
+    fn given_subcommands_should_parse_their_options() {
+        assert!(matches!(parse_args(&["help"]), Ok(Command::Help)));
+        assert!(matches!(
+            parse_args(&["disasm", "pong.ch8", "--origin", "0x300"]),
+            Ok(Command::Disasm { origin: 0x300, .. })
+        ));
+        assert!(matches!(
+            parse_args(&["asm", "pong.8o", "-o", "pong.ch8"]),
+            Ok(Command::Asm { output_path: Some(_), .. })
+        ));
+        assert!(matches!(parse_args(&["info", "pong.ch8"]), Ok(Command::Info { .. })));
+        match parse_args(&["bench", "pong.ch8", "--seconds", "5", "--ipf", "20", "--audio-out", "pong.wav"]) {
+            Ok(Command::Bench { seconds, audio_out, .. }) => assert_eq!((seconds, audio_out.as_deref()), (5, Some("pong.wav"))),
+            _ => panic!("not a bench command"),
+        }
+        match parse_args(&["test", "pong.ch8", "--frames", "10", "--expect", "ABC"]) {
+            Ok(Command::Test {
+                frames,
+                expect,
+                audio_out,
+                ..
+            }) => assert_eq!((frames, expect.as_deref(), audio_out), (10, Some("abc"), None)),
+            _ => panic!("not a test command"),
+        }
+        assert!(matches!(
+            parse_args(&["trace-diff", "a.log", "b.log"]),
+            Ok(Command::TraceDiff { .. })
+        ));
+    }
+
+    #[test_case(&[] ; "no arguments")]
+    #[test_case(&["run"] ; "run without rom")]
+    #[test_case(&["info"] ; "info without rom")]
+    #[test_case(&["pong.ch8", "tetris.ch8"] ; "two roms")]
+    #[test_case(&["info", "pong.ch8", "extra"] ; "extra argument")]
+    #[test_case(&["trace-diff", "a.log"] ; "trace-diff with one trace")]
+    #[test_case(&["--trace-diff", "a.log", "b.log"] ; "trace-diff as an option")]
+    fn given_missing_or_extra_arguments_should_fail(args: &[&str]) {
+        assert!(parse_args(args).is_err());
+    }
+
+    #[test]
+    fn given_unknown_option_should_name_it() {
+        assert_eq!(error(&["pong.ch8", "--turbo"]), "unknown option '--turbo'");
+        assert_eq!(error(&["disasm", "pong.ch8", "--scale", "2"]), "unknown option '--scale'");
+        assert_eq!(error(&["bench", "pong.ch8", "--mute"]), "unknown option '--mute'");
+    }
+
+    #[test]
+    fn given_option_without_value_should_fail() {
+        assert_eq!(error(&["pong.ch8", "--scale"]), "--scale expects a value");
+        assert_eq!(error(&["pong.ch8", "--fg"]), "--fg expects a value");
+        assert_eq!(error(&["pong.ch8", "--scale", "big"]), "invalid value 'big' for --scale");
+    }
+
+    #[test]
+    fn given_trace_from_should_take_address_or_break() {
+        let options = run_options(&["pong.ch8", "--trace-from", "0x2A0"]);
+        assert_eq!(options.trace_filter.start_address, Some(0x2A0));
+        assert!(!options.trace_filter.start_at_breakpoint);
+
+        let options = run_options(&["pong.ch8", "--trace-from", "break"]);
+        assert_eq!(options.trace_filter.start_address, None);
+        assert!(options.trace_filter.start_at_breakpoint);
+    }
+
+    #[test]
+    fn given_zero_scale_should_fail() {
+        assert_eq!(error(&["pong.ch8", "--scale", "0"]), "--scale must be at least 1");
+    }
+
+    #[test_case("ff8000", [0xFF, 0x80, 0x00, 0xFF] ; "plain")]
+    #[test_case("#0a0B0c", [0x0A, 0x0B, 0x0C, 0xFF] ; "with hash")]
+    fn given_color_should_parse_rgba(text: &str, color: [u8; 4]) {
+        assert_eq!(parse_color(text), Ok(color));
+    }
+
+    #[test_case("fff" ; "short")]
+    #[test_case("ff80001" ; "long")]
+    #[test_case("+fffff" ; "sign")]
+    #[test_case("gg0000" ; "not hex")]
+    fn given_invalid_color_should_fail(text: &str) {
+        assert!(parse_color(text).is_err());
+    }
+
+    #[test]
+    fn given_quirk_list_should_enable_each_quirk() {
+        let quirks = parse_quirks("shift, jump,wrap").unwrap();
+
+        assert!(quirks.shift_uses_vy && quirks.jump_uses_vx && quirks.wrap_sprites);
+        assert!(!quirks.load_store_increments_i && !quirks.logic_resets_vf);
+        assert_eq!(parse_quirks("none"), Ok(Quirks::default()));
+        assert_eq!(parse_quirks("shift,turbo"), Err(CliError(String::from("unknown quirk 'turbo'"))));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::cli::MachineOptions;
+use crate::cli::DEFAULT_VOLUME;
+use chip8::asm::assemble;
+use chip8::audio::Recorder;
+use chip8::audio::SAMPLE_RATE;
+use chip8::builder::Variant;
+use chip8::cpu::MemoryLayout;
+use chip8::disasm::*;
+use chip8::Machine;
+use std::fs::read;
+use std::fs::File;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Instant;
+
+const XO_CHIP_CLASSES: [&str; 2] = ["F002", "Fx3A"];
+
+pub fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    return read(path).map_err(|error| format!("cannot read '{}': {}", path, error));
+}
+
+// Headless machine, without drivers
+fn build_machine(rom_path: &str, options: &MachineOptions) -> Result<Machine, String> {
+    let rom = read_rom(rom_path)?;
+    let emulator = options
+        .builder()
+        .rom(rom)
+        .build()
+        .map_err(|error| format!("'{}' not loaded: {}", rom_path, error))?;
+    return Ok(emulator.into_machine());
+}
+
+// Sound of the frames, recorded only when a WAV file is given
+struct AudioOut {
+    path: String,
+    file: BufWriter<File>,
+    recorder: Recorder,
+}
+
+impl AudioOut {
+    // The file is created before running, a wrong path fails early
+    fn create(path: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        let file = File::create(path).map_err(|error| format!("cannot create '{}': {}", path, error))?;
+        let mut recorder = Recorder::new(SAMPLE_RATE);
+        recorder.mixer_mut().set_volume(DEFAULT_VOLUME as f32 / 100.0);
+        return Ok(Some(Self {
+            path: String::from(path),
+            file: BufWriter::new(file),
+            recorder: recorder,
+        }));
+    }
+
+    fn write(mut self) -> Result<(), String> {
+        return self
+            .recorder
+            .write_wav(&mut self.file)
+            .map_err(|error| format!("cannot write '{}': {}", self.path, error));
+    }
+}
+
+fn run_frames(machine: &mut Machine, frames: u64, audio_out: &mut Option<AudioOut>) {
+    for _i in 0..frames {
+        machine.run_frame();
+        if let Some(audio_out) = audio_out {
+            machine.play(&mut audio_out.recorder);
+        }
+    }
+}
+
+fn words(rom: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    return rom.chunks(2).map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]));
+}
+
+pub fn disasm(rom_path: &str, origin: u16) -> Result<(), String> {
+    let rom = read_rom(rom_path)?;
+    for (index, opcode) in words(&rom).enumerate() {
+        println!("0x{:03X}: {:04X}  {}", origin as usize + 2 * index, opcode, disassemble(opcode));
+    }
+    return Ok(());
+}
+
+// Output defaults to the source path with a .ch8 extension
+pub fn asm(source_path: &str, output_path: Option<&str>) -> Result<(), String> {
+    let source = read_to_string(source_path).map_err(|error| format!("cannot read '{}': {}", source_path, error))?;
+    let origin = MemoryLayout::default().program_start as u16;
+    let rom = assemble(&source, origin).map_err(|error| format!("{}:{}", source_path, error))?;
+    let output_path = match output_path {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => Path::new(source_path).with_extension("ch8"),
+    };
+    write(&output_path, &rom).map_err(|error| format!("cannot write '{}': {}", output_path.display(), error))?;
+    println!("'{}' written, {} bytes", output_path.display(), rom.len());
+    return Ok(());
+}
+
+pub fn info(rom_path: &str) -> Result<(), String> {
+    let rom = read_rom(rom_path)?;
+    let instructions = words(&rom).filter(|&opcode| opcode_class(opcode) != "????").count();
+    let xo_chip = words(&rom).any(|opcode| XO_CHIP_CLASSES.contains(&opcode_class(opcode)));
+    let available = Variant::Chip8.max_memory_size() - MemoryLayout::default().program_start;
+
+    println!("file:         {}", rom_path);
+    println!("size:         {} bytes", rom.len());
+    println!("sha1:         {}", sha1_smol::Sha1::from(&rom).digest());
+    println!("instructions: {} of {} words decode", instructions, words(&rom).count());
+    println!("xo-chip:      {}", if xo_chip { "uses XO-CHIP opcodes" } else { "no" });
+    if rom.len() > available {
+        println!("memory:       too large for 4 KB, needs --variant xochip");
+    }
+    return Ok(());
+}
+
+// Runs frames as fast as possible and compares the emulated time with the host time
+pub fn bench(rom_path: &str, seconds: u64, audio_out: Option<&str>, options: &MachineOptions) -> Result<(), String> {
+    let mut machine = build_machine(rom_path, options)?;
+    let frames = seconds * options.timer_frequency.unwrap_or(chip8::machine::TIMER_FREQUENCY) as u64;
+    let mut audio_out = AudioOut::create(audio_out)?;
+
+    let start = Instant::now();
+    run_frames(&mut machine, frames, &mut audio_out);
+    let elapsed = start.elapsed();
+
+    println!(
+        "{} frames ({} s emulated) in {:.3} s, {:.1}x real time",
+        frames,
+        seconds,
+        elapsed.as_secs_f64(),
+        seconds as f64 / elapsed.as_secs_f64()
+    );
+    if machine.cpu().is_halted() {
+        println!("cpu halted at 0x{:03X}", machine.cpu().program_counter());
+    }
+    if let Some(audio_out) = audio_out {
+        audio_out.write()?;
+    }
+    return Ok(());
+}
+
+// Prints the screen after the given number of frames, fails when its sha1 is not the expected one
+pub fn test(
+    rom_path: &str,
+    frames: u64,
+    expect: Option<&str>,
+    audio_out: Option<&str>,
+    options: &MachineOptions,
+) -> Result<(), String> {
+    let mut machine = build_machine(rom_path, options)?;
+    let mut audio_out = AudioOut::create(audio_out)?;
+    run_frames(&mut machine, frames, &mut audio_out);
+    if let Some(audio_out) = audio_out {
+        audio_out.write()?;
+    }
+
+    let framebuffer = machine.framebuffer();
+    let mut pixels = Vec::new();
+    for y in 0..framebuffer.height() {
+        let row: String = (0..framebuffer.width())
+            .map(|x| {
+                pixels.push(framebuffer.pixel(x, y));
+                if framebuffer.pixel(x, y) == 0 { '.' } else { '#' }
+            })
+            .collect();
+        println!("{}", row);
+    }
+
+    let hash = sha1_smol::Sha1::from(&pixels).digest().to_string();
+    println!("screen sha1: {}", hash);
+    return match expect {
+        Some(expected) if expected != hash => Err(format!("screen sha1 {} does not match {}", hash, expected)),
+        _ => Ok(()),
+    };
+}
@@ -0,0 +1,304 @@
+use crate::cli::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::Path;
+
+const CONFIG_DIRECTORY: &str = "sharke-chip8";
+const CONFIG_FILE: &str = "config.toml";
+
+// Same names and values as the command line options
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub variant: Option<String>,
+    pub quirks: Option<String>,
+    pub cpu_freq: Option<f64>,
+    pub timer_freq: Option<f64>,
+    pub ipf: Option<u32>,
+    pub speed: Option<SpeedSetting>,
+    pub theme: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub fg2: Option<String>,
+    pub blend: Option<String>,
+    pub keymap: Option<String>,
+    pub controller: Option<String>,
+    pub scale: Option<u32>,
+    pub anti_flicker: Option<String>,
+    pub fullscreen: Option<bool>,
+    pub waveform: Option<String>,
+    pub pitch: Option<u32>,
+    pub volume: Option<u8>,
+    pub mute: Option<bool>,
+}
+
+// `speed = 2` or `speed = "unlimited"`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SpeedSetting {
+    Multiplier(f64),
+    Name(String),
+}
+
+impl Settings {
+    // Values set in `other` replace the current ones
+    fn merge(&mut self, other: &Settings) {
+        fn set<T: Clone>(value: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                *value = other.clone();
+            }
+        }
+        set(&mut self.variant, &other.variant);
+        set(&mut self.quirks, &other.quirks);
+        set(&mut self.cpu_freq, &other.cpu_freq);
+        set(&mut self.timer_freq, &other.timer_freq);
+        set(&mut self.ipf, &other.ipf);
+        set(&mut self.speed, &other.speed);
+        set(&mut self.theme, &other.theme);
+        set(&mut self.fg, &other.fg);
+        set(&mut self.bg, &other.bg);
+        set(&mut self.fg2, &other.fg2);
+        set(&mut self.blend, &other.blend);
+        set(&mut self.keymap, &other.keymap);
+        set(&mut self.controller, &other.controller);
+        set(&mut self.scale, &other.scale);
+        set(&mut self.anti_flicker, &other.anti_flicker);
+        set(&mut self.fullscreen, &other.fullscreen);
+        set(&mut self.waveform, &other.waveform);
+        set(&mut self.pitch, &other.pitch);
+        set(&mut self.volume, &other.volume);
+        set(&mut self.mute, &other.mute);
+    }
+
+    // Only fills the options that were not given on the command line
+    // A command line variant keeps its own quirks and a command line cpu frequency is not replaced by an ipf
+    pub fn apply(&self, options: &mut RunOptions) -> Result<(), CliError> {
+        let machine = &mut options.machine;
+        let cli_variant = machine.variant.is_some();
+        let cli_cpu_frequency = machine.cpu_frequency.is_some();
+        if machine.variant.is_none() {
+            machine.variant = self.variant.as_deref().map(parse_variant).transpose()?;
+        }
+        if machine.quirks.is_none() && !cli_variant {
+            machine.quirks = self.quirks.as_deref().map(parse_quirks).transpose()?;
+        }
+        machine.cpu_frequency = machine.cpu_frequency.or(self.cpu_freq);
+        machine.timer_frequency = machine.timer_frequency.or(self.timer_freq);
+        if machine.instructions_per_frame.is_none() && !cli_cpu_frequency {
+            machine.instructions_per_frame = self.ipf;
+        }
+        if options.speed.is_none() {
+            options.speed = match &self.speed {
+                Some(SpeedSetting::Multiplier(multiplier)) => Some(parse_speed(&multiplier.to_string())?),
+                Some(SpeedSetting::Name(name)) => Some(parse_speed(name)?),
+                None => None,
+            };
+        }
+        if options.theme.is_none() {
+            options.theme = self.theme.as_deref().map(parse_theme).transpose()?;
+        }
+        if options.foreground.is_none() {
+            options.foreground = self.fg.as_deref().map(parse_color).transpose()?;
+        }
+        if options.background.is_none() {
+            options.background = self.bg.as_deref().map(parse_color).transpose()?;
+        }
+        if options.foreground2.is_none() {
+            options.foreground2 = self.fg2.as_deref().map(parse_color).transpose()?;
+        }
+        if options.blend.is_none() {
+            options.blend = self.blend.as_deref().map(parse_color).transpose()?;
+        }
+        if options.key_map.is_none() {
+            options.key_map = self.keymap.as_deref().map(parse_key_map).transpose()?;
+        }
+        if options.controller_map.is_none() {
+            options.controller_map = self.controller.as_deref().map(parse_controller_map).transpose()?;
+        }
+        options.scale = options.scale.or(self.scale);
+        if options.anti_flicker.is_none() {
+            options.anti_flicker = self.anti_flicker.as_deref().map(parse_anti_flicker).transpose()?;
+        }
+        options.fullscreen = options.fullscreen.or(self.fullscreen);
+        if options.waveform.is_none() {
+            options.waveform = self.waveform.as_deref().map(parse_waveform).transpose()?;
+        }
+        options.pitch = options.pitch.or(self.pitch);
+        options.volume = options.volume.or(self.volume.map(|volume| volume.min(100)));
+        options.mute = options.mute.or(self.mute);
+        return Ok(());
+    }
+}
+
+// Global settings at the top of the file, followed by per-rom tables keyed by sha1 or file name:
+//   variant = "schip"
+//   [rom."pong.ch8"]
+//   ipf = 15
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    global: Settings,
+    roms: HashMap<String, Settings>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let message = |error: toml::de::Error| error.message().to_string();
+        let mut table: toml::Table = toml::from_str(text).map_err(message)?;
+        let roms = match table.remove("rom") {
+            Some(roms) => roms.try_into().map_err(message)?,
+            None => HashMap::new(),
+        };
+        return Ok(Self {
+            global: toml::Value::Table(table).try_into().map_err(message)?,
+            roms: roms,
+        });
+    }
+
+    // "<user config dir>/sharke-chip8/config.toml", a missing file is an empty config
+    pub fn load_default() -> Result<Self, String> {
+        return match dirs::config_dir() {
+            Some(directory) => Self::load_optional(&directory.join(CONFIG_DIRECTORY).join(CONFIG_FILE)),
+            None => Ok(Self::default()),
+        };
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = read_to_string(path).map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+        return Self::parse(&text).map_err(|error| format!("'{}': {}", path.display(), error));
+    }
+
+    fn load_optional(path: &Path) -> Result<Self, String> {
+        return match read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|error| format!("'{}': {}", path.display(), error)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("cannot read '{}': {}", path.display(), error)),
+        };
+    }
+
+    // Global settings, overridden by the file name table, overridden by the sha1 table
+    pub fn settings_for(&self, rom_path: &Path, rom: &[u8]) -> Settings {
+        let mut settings = self.global.clone();
+        let file_name = rom_path.file_name().and_then(|name| name.to_str());
+        if let Some(rom_settings) = file_name.and_then(|name| self.roms.get(name)) {
+            settings.merge(rom_settings);
+        }
+        let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+        if let Some(rom_settings) = self.roms.get(&hash) {
+            settings.merge(rom_settings);
+        }
+        return settings;
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use chip8::builder::Variant;
+
+    const CONFIG: &str = r#"
+variant = "schip"
+ipf = 10
+fg = "33ff66"
+
+[rom."pong.ch8"]
+ipf = 15
+mute = true
+
+[rom."0123456789abcdef0123456789abcdef01234567"]
+ipf = 20
+"#;
+
+    fn run_options(args: &[&str]) -> RunOptions {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return match parse(&args).unwrap() {
+            Command::Run(options) => *options,
+            _ => panic!("not a run command"),
+        };
+    }
+
+    #[test]
+    fn given_config_when_parse_should_split_global_and_rom_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!(config.global.variant.as_deref(), Some("schip"));
+        assert_eq!(config.global.ipf, Some(10));
+        assert_eq!(config.roms.len(), 2);
+        assert_eq!(config.roms["pong.ch8"].mute, Some(true));
+    }
+
+    #[test]
+    fn given_unknown_key_when_parse_should_fail() {
+        assert!(Config::parse("colour = \"ff0000\"").is_err());
+        assert!(Config::parse("[rom.\"pong.ch8\"]\nipf = \"fast\"").is_err());
+    }
+
+    #[test]
+    fn given_rom_tables_when_settings_for_should_prefer_sha1_over_file_name() {
+        let hash = sha1_smol::Sha1::from([0x12, 0x00]).digest().to_string();
+        let config = Config::parse(&CONFIG.replace("0123456789abcdef0123456789abcdef01234567", &hash)).unwrap();
+
+        let by_name = config.settings_for(Path::new("roms/pong.ch8"), &[0x00, 0xE0]);
+        let by_hash = config.settings_for(Path::new("roms/pong.ch8"), &[0x12, 0x00]);
+        let global = config.settings_for(Path::new("roms/tetris.ch8"), &[0x00, 0xE0]);
+
+        assert_eq!((by_name.ipf, by_name.mute, by_name.fg.as_deref()), (Some(15), Some(true), Some("33ff66")));
+        assert_eq!((by_hash.ipf, by_hash.mute), (Some(20), Some(true)));
+        assert_eq!((global.ipf, global.mute), (Some(10), None));
+    }
+
+    #[test]
+    fn given_settings_when_merge_should_only_replace_set_values() {
+        let mut settings = Settings {
+            ipf: Some(10),
+            scale: Some(8),
+            ..Settings::default()
+        };
+
+        settings.merge(&Settings {
+            ipf: Some(20),
+            ..Settings::default()
+        });
+
+        assert_eq!((settings.ipf, settings.scale), (Some(20), Some(8)));
+    }
+
+    #[test]
+    fn given_command_line_options_when_apply_should_keep_them() {
+        let settings = Config::parse("variant = \"xochip\"\nquirks = \"none\"\nipf = 30\nfullscreen = true\nmute = true")
+            .unwrap()
+            .global;
+        let mut options = run_options(&["rom.ch8", "--variant", "chip8", "--cpu-freq", "700", "--no-fullscreen", "--no-mute"]);
+
+        settings.apply(&mut options).unwrap();
+
+        assert_eq!(options.machine.variant, Some(Variant::Chip8));
+        assert_eq!(options.machine.quirks, None);
+        assert_eq!(options.machine.cpu_frequency, Some(700.0));
+        assert_eq!(options.machine.instructions_per_frame, None);
+        assert_eq!((options.fullscreen, options.mute), (Some(false), Some(false)));
+    }
+
+    #[test]
+    fn given_no_command_line_options_when_apply_should_use_settings() {
+        let settings = Config::parse("variant = \"xochip\"\nquirks = \"wrap\"\nipf = 30\nfullscreen = true")
+            .unwrap()
+            .global;
+        let mut options = run_options(&["rom.ch8"]);
+
+        settings.apply(&mut options).unwrap();
+
+        assert_eq!(options.machine.variant, Some(Variant::XoChip));
+        assert!(options.machine.quirks.unwrap().wrap_sprites);
+        assert_eq!(options.machine.instructions_per_frame, Some(30));
+        assert_eq!((options.fullscreen, options.mute), (Some(true), None));
+    }
+
+    #[test]
+    fn given_invalid_value_when_apply_should_fail() {
+        let settings = Config::parse("variant = \"nes\"").unwrap().global;
+
+        assert!(settings.apply(&mut run_options(&["rom.ch8"])).is_err());
+    }
+}
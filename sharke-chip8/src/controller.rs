@@ -0,0 +1,278 @@
+use chip8::constants::KEY_COUNT;
+use chip8::driver::*;
+use sdl2::controller::Axis;
+use sdl2::controller::Button;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+pub const PROFILES: [&str; 4] = ["default", "pong", "tetris", "breakout"];
+
+// Half of the stick range, so diagonals press both directions
+const AXIS_THRESHOLD: i16 = 16384;
+
+// A button, or one direction of an axis ("leftx-", "lefty+", "righttrigger+")
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ControllerInput {
+    Button(Button),
+    Axis(Axis, bool),
+}
+
+impl ControllerInput {
+    // Names of the SDL game controller mappings
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(axis) = text.strip_suffix('+') {
+            return Axis::from_string(axis).map(|axis| ControllerInput::Axis(axis, true));
+        }
+        if let Some(axis) = text.strip_suffix('-') {
+            return Axis::from_string(axis).map(|axis| ControllerInput::Axis(axis, false));
+        }
+        return Button::from_string(text).map(ControllerInput::Button);
+    }
+}
+
+// Controller inputs bound to the Chip8 keys, shared by all the connected controllers
+#[derive(Clone, Debug, Default)]
+pub struct ControllerMap {
+    inputs: HashMap<ControllerInput, u8>,
+}
+
+impl ControllerMap {
+    pub fn new() -> Self {
+        return Self { inputs: HashMap::new() };
+    }
+
+    fn with_directions(up: u8, down: u8, left: u8, right: u8) -> Self {
+        let mut map = Self::new();
+        for (button, axis, positive, key) in [
+            (Button::DPadUp, Axis::LeftY, false, up),
+            (Button::DPadDown, Axis::LeftY, true, down),
+            (Button::DPadLeft, Axis::LeftX, false, left),
+            (Button::DPadRight, Axis::LeftX, true, right),
+        ] {
+            map.bind(ControllerInput::Button(button), key);
+            map.bind(ControllerInput::Axis(axis, positive), key);
+        }
+        return map;
+    }
+
+    // Directions on 2, 8, 4, 6 and A on 5, like the arrows keyboard layout
+    pub fn default_profile() -> Self {
+        let mut map = Self::with_directions(KEY_2, KEY_8, KEY_4, KEY_6);
+        map.bind(ControllerInput::Button(Button::A), KEY_5);
+        map.bind(ControllerInput::Button(Button::B), KEY_0);
+        map.bind(ControllerInput::Button(Button::Start), KEY_F);
+        return map;
+    }
+
+    // Left paddle on 1 and 4, right paddle on C and D with the right stick
+    pub fn pong() -> Self {
+        let mut map = Self::new();
+        map.bind(ControllerInput::Button(Button::DPadUp), KEY_1);
+        map.bind(ControllerInput::Button(Button::DPadDown), KEY_4);
+        map.bind(ControllerInput::Axis(Axis::LeftY, false), KEY_1);
+        map.bind(ControllerInput::Axis(Axis::LeftY, true), KEY_4);
+        map.bind(ControllerInput::Axis(Axis::RightY, false), KEY_C);
+        map.bind(ControllerInput::Axis(Axis::RightY, true), KEY_D);
+        return map;
+    }
+
+    // Rotate on 4, move on 5 and 6, drop on 7
+    pub fn tetris() -> Self {
+        let mut map = Self::with_directions(KEY_4, KEY_7, KEY_5, KEY_6);
+        map.bind(ControllerInput::Button(Button::A), KEY_4);
+        return map;
+    }
+
+    // Paddle on 4 and 6, also used by Brix
+    pub fn breakout() -> Self {
+        let mut map = Self::new();
+        map.bind(ControllerInput::Button(Button::DPadLeft), KEY_4);
+        map.bind(ControllerInput::Button(Button::DPadRight), KEY_6);
+        map.bind(ControllerInput::Axis(Axis::LeftX, false), KEY_4);
+        map.bind(ControllerInput::Axis(Axis::LeftX, true), KEY_6);
+        return map;
+    }
+
+    pub fn profile(name: &str) -> Option<Self> {
+        return match name {
+            "default" => Some(Self::default_profile()),
+            "pong" => Some(Self::pong()),
+            "tetris" => Some(Self::tetris()),
+            "breakout" => Some(Self::breakout()),
+            _ => None,
+        };
+    }
+
+    // Profile of a well known game from the rom file name, the default one otherwise
+    pub fn for_rom(file_name: &str) -> Self {
+        let file_name = file_name.to_ascii_lowercase();
+        let profile = ["pong", "tetris", "breakout", "brix"]
+            .into_iter()
+            .find(|game| file_name.contains(game))
+            .map(|game| if game == "brix" { "breakout" } else { game })
+            .unwrap_or("default");
+        return Self::profile(profile).unwrap();
+    }
+
+    pub fn bind(&mut self, input: ControllerInput, key: u8) {
+        self.inputs.insert(input, key);
+    }
+
+    pub fn key_for(&self, input: ControllerInput) -> Option<u8> {
+        return self.inputs.get(&input).copied();
+    }
+
+    // Comma separated profile names and "<hex key>=<input>" bindings, added in order:
+    //   "default,1=leftshoulder,C=rightshoulder"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = Self::new();
+        for item in text.split(',').map(|item| item.trim()) {
+            if let Some(profile) = Self::profile(item) {
+                map.inputs.extend(profile.inputs);
+                continue;
+            }
+            let (key, name) = item
+                .split_once('=')
+                .ok_or_else(|| format!("unknown profile '{}', expected one of {}", item, PROFILES.join(", ")))?;
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| (key as usize) < KEY_COUNT)
+                .ok_or_else(|| format!("invalid Chip8 key '{}'", key))?;
+            let input = ControllerInput::parse(name).ok_or_else(|| format!("unknown controller input '{}'", name))?;
+            map.bind(input, key);
+        }
+        return Ok(map);
+    }
+}
+
+// Whether an axis at `value` is pushed far enough in the positive or negative direction
+fn is_pushed(value: i16, positive: bool) -> bool {
+    let direction = if positive { value } else { value.saturating_neg() };
+    return direction > AXIS_THRESHOLD;
+}
+
+// Opens controllers when they are plugged, SDL also reports the ones connected at startup
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+    map: ControllerMap,
+    pressed: HashSet<(u32, ControllerInput)>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem, map: ControllerMap) -> Self {
+        return Self {
+            subsystem: subsystem,
+            controllers: HashMap::new(),
+            map: map,
+            pressed: HashSet::new(),
+        };
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(*which) {
+                Ok(controller) => {
+                    println!("controller: '{}' connected", controller.name());
+                    self.controllers.insert(controller.instance_id(), controller);
+                }
+                Err(error) => println!("controller: {}", error),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.controllers.remove(which) {
+                    println!("controller: '{}' disconnected", controller.name());
+                }
+                self.pressed.retain(|(id, _)| id != which);
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.pressed.insert((*which, ControllerInput::Button(*button)));
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.pressed.remove(&(*which, ControllerInput::Button(*button)));
+            }
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                for positive in [true, false] {
+                    let input = (*which, ControllerInput::Axis(*axis, positive));
+                    if is_pushed(*value, positive) {
+                        self.pressed.insert(input);
+                    } else {
+                        self.pressed.remove(&input);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_key_down(&self, key: u8) -> bool {
+        return self
+            .pressed
+            .iter()
+            .any(|(_, input)| self.map.key_for(*input) == Some(key));
+    }
+}
+
+#[cfg(test)]
+mod controller_tests {
+    use super::is_pushed;
+    use super::ControllerInput;
+    use super::ControllerMap;
+    use super::AXIS_THRESHOLD;
+    use chip8::driver::*;
+    use sdl2::controller::Axis;
+    use sdl2::controller::Button;
+
+    fn button(map: &ControllerMap, button: Button) -> Option<u8> {
+        return map.key_for(ControllerInput::Button(button));
+    }
+
+    #[test]
+    fn given_profile_and_bindings_when_parse_should_add_them_in_order() {
+        let map = ControllerMap::parse("default, 1=leftshoulder, C=righty-").unwrap();
+
+        assert_eq!(button(&map, Button::A), Some(KEY_5));
+        assert_eq!(button(&map, Button::LeftShoulder), Some(KEY_1));
+        assert_eq!(map.key_for(ControllerInput::Axis(Axis::RightY, false)), Some(KEY_C));
+    }
+
+    #[test]
+    fn given_binding_after_profile_when_parse_should_replace_profile_key() {
+        let map = ControllerMap::parse("default,0=a").unwrap();
+
+        assert_eq!(button(&map, Button::A), Some(KEY_0));
+    }
+
+    #[test]
+    fn given_invalid_items_when_parse_should_fail() {
+        assert!(ControllerMap::parse("zelda").is_err());
+        assert!(ControllerMap::parse("10=a").is_err());
+        assert!(ControllerMap::parse("G=a").is_err());
+        assert!(ControllerMap::parse("1=wheel").is_err());
+    }
+
+    #[test]
+    fn given_rom_names_when_for_rom_should_pick_matching_profile() {
+        assert_eq!(button(&ControllerMap::for_rom("PONG2.ch8"), Button::DPadUp), Some(KEY_1));
+        assert_eq!(button(&ControllerMap::for_rom("brix.ch8"), Button::DPadLeft), Some(KEY_4));
+        assert_eq!(button(&ControllerMap::for_rom("tetris [Fran Dachille].ch8"), Button::A), Some(KEY_4));
+        assert_eq!(button(&ControllerMap::for_rom("invaders.ch8"), Button::A), Some(KEY_5));
+    }
+
+    #[test]
+    fn given_axis_values_when_is_pushed_should_require_more_than_threshold() {
+        assert!(!is_pushed(AXIS_THRESHOLD, true));
+        assert!(is_pushed(AXIS_THRESHOLD + 1, true));
+        assert!(!is_pushed(AXIS_THRESHOLD + 1, false));
+        assert!(is_pushed(-AXIS_THRESHOLD - 1, false));
+        assert!(!is_pushed(-AXIS_THRESHOLD, false));
+    }
+
+    #[test]
+    fn given_full_negative_axis_when_is_pushed_should_not_overflow() {
+        assert!(is_pushed(i16::MIN, false));
+        assert!(!is_pushed(i16::MIN, true));
+    }
+}
@@ -0,0 +1,381 @@
+use chip8::cheats::*;
+use chip8::cpu::Cpu;
+use chip8::cpu::HaltReason;
+use chip8::disasm::disassemble;
+use std::collections::VecDeque;
+use std::fs::read_to_string;
+use std::io::stdin;
+use std::io::stdout;
+use std::io::Write;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+
+const PROMPT: &str = "(chip8) ";
+const DEFAULT_MEMORY_LENGTH: usize = 16;
+const DEFAULT_DISASM_COUNT: usize = 8;
+const CALL_OPCODE: u16 = 0x2;
+const SEARCH_RESULTS: usize = 16;
+
+const HELP: &str = "\
+break [addr]        set a breakpoint, or list breakpoints
+delete addr         remove a breakpoint
+watch [addr]        stop when a memory byte changes, or list watchpoints
+unwatch addr        remove a watchpoint
+step [count]        execute instructions
+next                execute instruction, stepping over subroutine calls
+continue            run until a breakpoint or watchpoint
+regs                show registers
+stack               show call stack
+mem addr [len]      dump memory
+disasm [addr] [n]   disassemble instructions
+set reg value       set v0-vf, i, pc or sp
+poke addr value     write a memory byte
+search              start a memory search from a snapshot
+search cond [value] keep addresses matching eq value, changed, unchanged, inc or dec
+reset               reset the machine
+source file         run commands from a file
+quit                exit the emulator";
+
+pub enum DebuggerEvent {
+    None,
+    Reset,
+    Quit,
+}
+
+pub struct Debugger {
+    input: Receiver<String>,
+    pending: VecDeque<String>,
+    running: bool,
+    temporary_breakpoint: Option<u16>,
+    prompt_shown: bool,
+    search: Option<MemorySearch>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for line in stdin().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+
+        return Self {
+            input: receiver,
+            pending: VecDeque::new(),
+            running: false,
+            temporary_breakpoint: None,
+            prompt_shown: false,
+            search: None,
+        };
+    }
+
+    pub fn source(&mut self, path: &str) -> std::io::Result<()> {
+        let script = read_to_string(path)?;
+        for line in script.lines().rev() {
+            self.pending.push_front(line.to_string());
+        }
+        return Ok(());
+    }
+
+    pub fn poll(&mut self, cpu: &mut Cpu) -> DebuggerEvent {
+        loop {
+            match self.input.try_recv() {
+                Ok(line) => self.pending.push_back(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.pending.is_empty() && cpu.is_halted() {
+                        return DebuggerEvent::Quit;
+                    }
+                    break;
+                }
+            }
+        }
+
+        if self.running {
+            if !cpu.is_halted() {
+                return DebuggerEvent::None;
+            }
+            self.running = false;
+            self.report_stop(cpu);
+        }
+
+        while let Some(line) = self.pending.pop_front() {
+            self.prompt_shown = false;
+            let event = self.execute(line.trim(), cpu);
+            if !matches!(event, DebuggerEvent::None) || self.running {
+                return event;
+            }
+        }
+
+        if !self.prompt_shown {
+            print!("{}", PROMPT);
+            let _ = stdout().flush();
+            self.prompt_shown = true;
+        }
+        return DebuggerEvent::None;
+    }
+
+    fn execute(&mut self, line: &str, cpu: &mut Cpu) -> DebuggerEvent {
+        let arguments: Vec<&str> = line.split_whitespace().collect();
+        if arguments.is_empty() || arguments[0].starts_with('#') {
+            return DebuggerEvent::None;
+        }
+
+        match (arguments[0], &arguments[1..]) {
+            ("break" | "b", []) => {
+                for address in cpu.breakpoints() {
+                    println!("breakpoint at 0x{:03X}", address);
+                }
+            }
+            ("break" | "b", [address]) => match parse_address(address, cpu) {
+                Some(address) => cpu.add_breakpoint(address),
+                None => println!("invalid address '{}'", address),
+            },
+            ("delete" | "d", [address]) => match parse_address(address, cpu) {
+                Some(address) => cpu.remove_breakpoint(address),
+                None => println!("invalid address '{}'", address),
+            },
+            ("watch" | "w", []) => {
+                for address in cpu.watchpoints() {
+                    println!("watchpoint at 0x{:03X}", address);
+                }
+            }
+            ("watch" | "w", [address]) => match parse_address(address, cpu) {
+                Some(address) => cpu.add_watchpoint(address),
+                None => println!("invalid address '{}'", address),
+            },
+            ("unwatch", [address]) => match parse_address(address, cpu) {
+                Some(address) => cpu.remove_watchpoint(address),
+                None => println!("invalid address '{}'", address),
+            },
+            ("step" | "s", count) => {
+                let count = count.first().and_then(|value| parse_number(value)).unwrap_or(1);
+                for _i in 0..count {
+                    if step_instruction(cpu) {
+                        break;
+                    }
+                }
+                self.report_stop(cpu);
+            }
+            ("next" | "n", []) => {
+                let pc = cpu.program_counter();
+                let is_call = cpu.read_opcode(pc as usize).map(|opcode| opcode >> 12) == Some(CALL_OPCODE);
+                if is_call {
+                    let return_address = pc + 2;
+                    if !cpu.breakpoints().any(|&address| address == return_address) {
+                        cpu.add_breakpoint(return_address);
+                        self.temporary_breakpoint = Some(return_address);
+                    }
+                    if step_instruction(cpu) {
+                        self.report_stop(cpu);
+                    } else {
+                        self.continue_execution(cpu);
+                    }
+                } else {
+                    step_instruction(cpu);
+                    self.report_stop(cpu);
+                }
+            }
+            ("continue" | "c", []) => self.continue_execution(cpu),
+            ("regs" | "r", []) => print_registers(cpu),
+            ("stack", []) => {
+                for (depth, address) in cpu.stack().iter().enumerate().rev() {
+                    println!("#{} 0x{:03X}", depth, address);
+                }
+            }
+            ("mem" | "x", [address, length @ ..]) => {
+                let length = length
+                    .first()
+                    .and_then(|value| parse_number(value))
+                    .unwrap_or(DEFAULT_MEMORY_LENGTH);
+                match parse_address(address, cpu) {
+                    Some(address) => print_memory(cpu, address as usize, length),
+                    None => println!("invalid address '{}'", address),
+                }
+            }
+            ("disasm" | "u", arguments) => {
+                let address = match arguments.first() {
+                    Some(address) => parse_address(address, cpu),
+                    None => Some(cpu.program_counter()),
+                };
+                let count = arguments
+                    .get(1)
+                    .and_then(|value| parse_number(value))
+                    .unwrap_or(DEFAULT_DISASM_COUNT);
+                match address {
+                    Some(address) => print_disassembly(cpu, address as usize, count),
+                    None => println!("invalid address '{}'", arguments[0]),
+                }
+            }
+            ("set", [register, value]) => match parse_number(value) {
+                Some(value) => {
+                    if let Err(error) = set_register(cpu, register, value) {
+                        println!("{}", error);
+                    }
+                }
+                None => println!("invalid value '{}'", value),
+            },
+            ("poke", [target, value]) => match (parse_target(target), parse_number(value)) {
+                (Some(target), Some(value)) if value <= u8::MAX as usize => write_target(cpu, target, value as u8),
+                _ => println!("invalid poke '{}'", line),
+            },
+            ("search", []) => {
+                self.search = Some(MemorySearch::new(cpu));
+                println!("{} candidates", cpu.memory_size());
+            }
+            ("search", [condition, value @ ..]) => {
+                let condition = match (*condition, value) {
+                    ("eq", [value]) => parse_number(value)
+                        .filter(|&value| value <= u8::MAX as usize)
+                        .map(|value| SearchCondition::Equal(value as u8)),
+                    ("changed", []) => Some(SearchCondition::Changed),
+                    ("unchanged", []) => Some(SearchCondition::Unchanged),
+                    ("inc", []) => Some(SearchCondition::Increased),
+                    ("dec", []) => Some(SearchCondition::Decreased),
+                    _ => None,
+                };
+                match (condition, self.search.as_mut()) {
+                    (Some(condition), Some(search)) => {
+                        search.filter(cpu, condition);
+                        print_candidates(cpu, search.candidates());
+                    }
+                    (_, None) => println!("no search in progress, type 'search' first"),
+                    (None, _) => println!("unknown search condition '{}'", line),
+                }
+            }
+            ("reset", []) => return DebuggerEvent::Reset,
+            ("source", [path]) => {
+                if let Err(error) = self.source(path) {
+                    println!("cannot read '{}' ({})", path, error);
+                }
+            }
+            ("help" | "h", []) => println!("{}", HELP),
+            ("quit" | "q", []) => return DebuggerEvent::Quit,
+            _ => println!("unknown command '{}', type 'help'", line),
+        }
+        return DebuggerEvent::None;
+    }
+
+    fn continue_execution(&mut self, cpu: &mut Cpu) {
+        cpu.resume();
+        self.running = true;
+    }
+
+    fn report_stop(&mut self, cpu: &mut Cpu) {
+        if let Some(address) = self.temporary_breakpoint.take() {
+            cpu.remove_breakpoint(address);
+        }
+
+        match cpu.halt_reason() {
+            Some(HaltReason::Breakpoint(address)) => println!("breakpoint at 0x{:03X}", address),
+            Some(HaltReason::Watchpoint { address, old, new }) => {
+                println!("watchpoint 0x{:03X}: 0x{:02X} -> 0x{:02X}", address, old, new)
+            }
+            _ => {}
+        }
+        print_current_instruction(cpu);
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    return match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse::<usize>().ok(),
+    };
+}
+
+fn parse_address(text: &str, cpu: &Cpu) -> Option<u16> {
+    return parse_number(text)
+        .filter(|&address| address < cpu.memory_size())
+        .map(|address| address as u16);
+}
+
+// Stepping starts from a halted cpu, true when the instruction hit a watchpoint
+fn step_instruction(cpu: &mut Cpu) -> bool {
+    cpu.halt();
+    cpu.step();
+    return matches!(cpu.halt_reason(), Some(HaltReason::Watchpoint { .. }));
+}
+
+fn set_register(cpu: &mut Cpu, name: &str, value: usize) -> Result<(), String> {
+    let register = name.to_lowercase();
+    let word = || u16::try_from(value).map_err(|_| format!("invalid value 0x{:X}", value));
+    match register.as_str() {
+        "i" => cpu.set_i(value as u16),
+        "pc" => cpu.set_program_counter(word()?).map_err(|error| error.to_string())?,
+        "sp" => cpu.set_stack_pointer(word()?).map_err(|error| error.to_string())?,
+        _ => {
+            let index = register
+                .strip_prefix('v')
+                .and_then(|index| usize::from_str_radix(index, 16).ok());
+            match index {
+                Some(index) if index < 16 => cpu.set_v(index, value as u8),
+                _ => return Err(format!("unknown register '{}'", name)),
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn print_candidates(cpu: &Cpu, candidates: &[u16]) {
+    println!("{} candidates", candidates.len());
+    for address in candidates.iter().take(SEARCH_RESULTS) {
+        println!("  0x{:03X} = 0x{:02X}", address, cpu.read_memory(*address as usize).unwrap());
+    }
+}
+
+fn print_current_instruction(cpu: &Cpu) {
+    print_disassembly(cpu, cpu.program_counter() as usize, 1);
+}
+
+fn print_registers(cpu: &Cpu) {
+    for row in 0..2 {
+        let registers: Vec<String> = (0..8)
+            .map(|column| row * 8 + column)
+            .map(|index| format!("V{:X}={:02X}", index, cpu.v(index)))
+            .collect();
+        println!("{}", registers.join(" "));
+    }
+    println!(
+        "I={:03X} PC={:03X} SP={:X}",
+        cpu.i(),
+        cpu.program_counter(),
+        cpu.stack_pointer()
+    );
+}
+
+fn print_memory(cpu: &Cpu, address: usize, length: usize) {
+    let end = (address + length).min(cpu.memory_size());
+    for line in (address..end).step_by(16) {
+        let bytes: Vec<String> = (line..(line + 16).min(end))
+            .map(|offset| format!("{:02X}", cpu.read_memory(offset).unwrap()))
+            .collect();
+        println!("{:03X}: {}", line, bytes.join(" "));
+    }
+}
+
+fn print_disassembly(cpu: &Cpu, address: usize, count: usize) {
+    for index in 0..count {
+        let address = address + index * 2;
+        let opcode = match cpu.read_opcode(address) {
+            Some(opcode) => opcode,
+            None => return,
+        };
+        let marker = if address == cpu.program_counter() as usize {
+            "=>"
+        } else {
+            "  "
+        };
+        println!("{} {:03X}: {:04X}  {}", marker, address, opcode, disassemble(opcode));
+    }
+}
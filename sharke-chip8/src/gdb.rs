@@ -0,0 +1,451 @@
+use chip8::cpu::Cpu;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+const INTERRUPT: u8 = 0x03;
+const PACKET_SIZE: usize = 0x1000;
+
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.sharke.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+pub struct GdbStub {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    running: bool,
+}
+
+impl GdbStub {
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        return Ok(Self {
+            listener: listener,
+            stream: None,
+            input: Vec::new(),
+            running: false,
+        });
+    }
+
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        if self.stream.is_none() {
+            self.accept(cpu);
+        }
+
+        if self.stream.is_none() {
+            return;
+        }
+
+        if !self.receive() {
+            self.disconnect(cpu);
+            return;
+        }
+
+        self.process_input(cpu);
+
+        if self.running && cpu.is_halted() {
+            self.running = false;
+            self.send_packet("S05");
+        }
+    }
+
+    fn accept(&mut self, cpu: &mut Cpu) {
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                if stream.set_nonblocking(true).is_err() {
+                    return;
+                }
+                let _ = stream.set_nodelay(true);
+                println!("gdb: connection from {}", address);
+                self.stream = Some(stream);
+                self.input.clear();
+                self.running = false;
+                cpu.halt();
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) => println!("gdb: accept failed ({})", error),
+        }
+    }
+
+    fn disconnect(&mut self, cpu: &mut Cpu) {
+        println!("gdb: connection closed");
+        self.stream = None;
+        self.input.clear();
+        self.running = false;
+        cpu.resume();
+    }
+
+    fn receive(&mut self) -> bool {
+        let stream = self.stream.as_mut().unwrap();
+        let mut buffer = [0u8; 1024];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(count) => self.input.extend_from_slice(&buffer[..count]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn process_input(&mut self, cpu: &mut Cpu) {
+        loop {
+            match self.input.first() {
+                None => return,
+                Some(b'+') | Some(b'-') => {
+                    self.input.remove(0);
+                }
+                Some(&INTERRUPT) => {
+                    self.input.remove(0);
+                    cpu.halt();
+                    if self.running {
+                        self.running = false;
+                        self.send_packet("S02");
+                    }
+                }
+                Some(b'$') => {
+                    let end = match self.input.iter().position(|&b| b == b'#') {
+                        Some(end) if end + 2 < self.input.len() => end,
+                        _ => return,
+                    };
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok());
+                    if checksum != Some(compute_checksum(data)) {
+                        self.send_raw(b"-");
+                        continue;
+                    }
+                    self.send_raw(b"+");
+
+                    let command = String::from_utf8_lossy(data).to_string();
+                    if let Some(response) = self.handle_packet(&command, cpu) {
+                        self.send_packet(&response);
+                    }
+
+                    if self.stream.is_none() {
+                        return;
+                    }
+                }
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, command: &str, cpu: &mut Cpu) -> Option<String> {
+        if command.is_empty() || !command.is_char_boundary(1) {
+            return Some(String::new());
+        }
+
+        let (kind, arguments) = command.split_at(1);
+        return match kind {
+            "?" => Some(String::from("S05")),
+            "g" => Some(read_registers(cpu)),
+            "G" => Some(reply(write_registers(cpu, arguments))),
+            "p" => Some(
+                usize::from_str_radix(arguments, 16)
+                    .ok()
+                    .and_then(|register| read_register(cpu, register))
+                    .unwrap_or_else(|| String::from("E01")),
+            ),
+            "P" => Some(reply(write_register(cpu, arguments))),
+            "m" => Some(read_memory(cpu, arguments).unwrap_or_else(|| String::from("E01"))),
+            "M" => Some(reply(write_memory(cpu, arguments))),
+            "Z" | "z" => Some(update_breakpoint(cpu, kind == "Z", arguments)),
+            "s" => {
+                if !resume_address(cpu, arguments) {
+                    return Some(String::from("E01"));
+                }
+                cpu.step();
+                Some(String::from("S05"))
+            }
+            "c" => {
+                if !resume_address(cpu, arguments) {
+                    return Some(String::from("E01"));
+                }
+                cpu.resume();
+                self.running = true;
+                None
+            }
+            "D" => {
+                self.send_packet("OK");
+                self.disconnect(cpu);
+                None
+            }
+            "k" => {
+                self.disconnect(cpu);
+                None
+            }
+            "H" => Some(String::from("OK")),
+            "q" => Some(handle_query(command)),
+            _ => Some(String::new()),
+        };
+    }
+
+    fn send_packet(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, compute_checksum(data.as_bytes()));
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, data: &[u8]) {
+        if let Some(stream) = self.stream.as_mut() {
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.write_all(data);
+            let _ = stream.set_nonblocking(true);
+        }
+    }
+}
+
+fn handle_query(command: &str) -> String {
+    if command.starts_with("qSupported") {
+        return format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE);
+    }
+
+    if let Some(arguments) = command.strip_prefix("qXfer:features:read:target.xml:") {
+        let (offset, length) = match parse_address_length(arguments) {
+            Some(value) => value,
+            None => return String::from("E01"),
+        };
+        let document = TARGET_XML.as_bytes();
+        if offset >= document.len() {
+            return String::from("l");
+        }
+        let end = (offset + length).min(document.len());
+        let chunk = String::from_utf8_lossy(&document[offset..end]);
+        return format!("{}{}", if end == document.len() { "l" } else { "m" }, chunk);
+    }
+
+    return match command {
+        "qAttached" => String::from("1"),
+        "qC" => String::from("QC1"),
+        "qfThreadInfo" => String::from("m1"),
+        "qsThreadInfo" => String::from("l"),
+        _ => String::new(),
+    };
+}
+
+fn reply(ok: bool) -> String {
+    return String::from(if ok { "OK" } else { "E01" });
+}
+
+fn read_register(cpu: &Cpu, register: usize) -> Option<String> {
+    return match register {
+        0..=15 => Some(format!("{:02x}", cpu.v(register))),
+        REGISTER_I => Some(encode_u16(cpu.i())),
+        REGISTER_PC => Some(encode_u16(cpu.program_counter())),
+        REGISTER_SP => Some(format!("{:02x}", cpu.stack_pointer() as u8)),
+        _ => None,
+    };
+}
+
+fn read_registers(cpu: &Cpu) -> String {
+    let mut registers = String::new();
+    for register in 0..=REGISTER_SP {
+        registers.push_str(&read_register(cpu, register).unwrap());
+    }
+    return registers;
+}
+
+fn write_register(cpu: &mut Cpu, arguments: &str) -> bool {
+    let (register, value) = match arguments.split_once('=') {
+        Some(value) => value,
+        None => return false,
+    };
+    let register = match usize::from_str_radix(register, 16) {
+        Ok(register) => register,
+        Err(_) => return false,
+    };
+    let bytes = match decode_hex(value) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    return set_register(cpu, register, &bytes);
+}
+
+fn write_registers(cpu: &mut Cpu, arguments: &str) -> bool {
+    let bytes = match decode_hex(arguments) {
+        Some(bytes) => bytes,
+        None => return false,
+    };
+    let mut offset = 0;
+    for register in 0..=REGISTER_SP {
+        let size = register_size(register);
+        if offset + size > bytes.len() {
+            return false;
+        }
+        set_register(cpu, register, &bytes[offset..offset + size]);
+        offset += size;
+    }
+    return true;
+}
+
+fn register_size(register: usize) -> usize {
+    return if register == REGISTER_I || register == REGISTER_PC {
+        2
+    } else {
+        1
+    };
+}
+
+// Invalid pc or sp values are refused, they would crash the emulator
+fn set_register(cpu: &mut Cpu, register: usize, bytes: &[u8]) -> bool {
+    if bytes.len() < register_size(register) {
+        return false;
+    }
+    match register {
+        0..=15 => cpu.set_v(register, bytes[0]),
+        REGISTER_I => cpu.set_i(u16::from_le_bytes([bytes[0], bytes[1]])),
+        REGISTER_PC => return cpu.set_program_counter(u16::from_le_bytes([bytes[0], bytes[1]])).is_ok(),
+        REGISTER_SP => return cpu.set_stack_pointer(bytes[0] as u16).is_ok(),
+        _ => return false,
+    }
+    return true;
+}
+
+// Optional address of the "s" and "c" packets, false when it is not a valid pc
+fn resume_address(cpu: &mut Cpu, arguments: &str) -> bool {
+    if arguments.is_empty() {
+        return true;
+    }
+    return parse_hex(arguments)
+        .and_then(|address| u16::try_from(address).ok())
+        .is_some_and(|address| cpu.set_program_counter(address).is_ok());
+}
+
+fn read_memory(cpu: &Cpu, arguments: &str) -> Option<String> {
+    let (address, length) = parse_address_length(arguments)?;
+    let mut data = String::new();
+    for offset in 0..length {
+        data.push_str(&format!("{:02x}", cpu.read_memory(address + offset)?));
+    }
+    return Some(data);
+}
+
+fn write_memory(cpu: &mut Cpu, arguments: &str) -> bool {
+    let (header, data) = match arguments.split_once(':') {
+        Some(value) => value,
+        None => return false,
+    };
+    let (address, length) = match parse_address_length(header) {
+        Some(value) => value,
+        None => return false,
+    };
+    let bytes = match decode_hex(data) {
+        Some(bytes) if bytes.len() == length => bytes,
+        _ => return false,
+    };
+    if address + length > cpu.memory_size() {
+        return false;
+    }
+    for (offset, value) in bytes.iter().enumerate() {
+        cpu.write_memory(address + offset, *value);
+    }
+    return true;
+}
+
+fn update_breakpoint(cpu: &mut Cpu, insert: bool, arguments: &str) -> String {
+    let mut fields = arguments.split(',');
+    if fields.next() != Some("0") {
+        return String::new();
+    }
+    // Addresses past 0xFFFF are refused rather than wrapped
+    let address = fields.next().and_then(parse_hex);
+    let address = match address.and_then(|address| u16::try_from(address).ok()) {
+        Some(address) => address,
+        None => return String::from("E01"),
+    };
+    if insert {
+        cpu.add_breakpoint(address);
+    } else {
+        cpu.remove_breakpoint(address);
+    }
+    return String::from("OK");
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    return usize::from_str_radix(text, 16).ok();
+}
+
+fn parse_address_length(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    return Some((parse_hex(address)?, parse_hex(length)?));
+}
+
+fn encode_u16(value: u16) -> String {
+    let bytes = value.to_le_bytes();
+    return format!("{:02x}{:02x}", bytes[0], bytes[1]);
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    return (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect();
+}
+
+fn compute_checksum(data: &[u8]) -> u8 {
+    return data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+}
+
+#[cfg(test)]
+mod gdb_tests {
+    use super::update_breakpoint;
+    use chip8::cpu::Cpu;
+    use chip8::machine::TIMER_FREQUENCY;
+
+    #[test]
+    fn given_z0_packet_when_update_breakpoint_should_add_and_remove_it() {
+        let mut cpu = Cpu::new(TIMER_FREQUENCY);
+
+        assert_eq!(update_breakpoint(&mut cpu, true, "0,204,2"), "OK");
+        assert_eq!(cpu.breakpoints().copied().collect::<Vec<u16>>(), vec![0x204]);
+        assert_eq!(update_breakpoint(&mut cpu, false, "0,204,2"), "OK");
+        assert_eq!(cpu.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn given_out_of_range_address_when_update_breakpoint_should_reply_e01() {
+        let mut cpu = Cpu::new(TIMER_FREQUENCY);
+
+        assert_eq!(update_breakpoint(&mut cpu, true, "0,10200,2"), "E01");
+        assert_eq!(cpu.breakpoints().count(), 0);
+    }
+}
@@ -0,0 +1,239 @@
+use chip8::constants::KEY_COUNT;
+use chip8::driver::*;
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+
+pub const LAYOUTS: [&str; 4] = ["qwerty", "azerty", "numpad", "arrows"];
+
+// Host keys bound to each Chip8 key, several host keys can press the same Chip8 key
+#[derive(Clone, Debug, Default)]
+pub struct KeyMap {
+    keys: HashMap<Keycode, u8>,
+    // Reverse of `keys`, looked up for every Chip8 key on every frame
+    keycodes: [Vec<Keycode>; KEY_COUNT],
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        return Self {
+            keys: HashMap::new(),
+            keycodes: Default::default(),
+        };
+    }
+
+    // Chip8 keypad on the left of the keyboard, see README
+    pub fn qwerty() -> Self {
+        return Self::from_rows([
+            [Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4],
+            [Keycode::Q, Keycode::W, Keycode::E, Keycode::R],
+            [Keycode::A, Keycode::S, Keycode::D, Keycode::F],
+            [Keycode::Z, Keycode::X, Keycode::C, Keycode::V],
+        ]);
+    }
+
+    // Same key positions as qwerty, on a french keyboard
+    // SDL reports the number row as digits on every layout
+    pub fn azerty() -> Self {
+        return Self::from_rows([
+            [Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4],
+            [Keycode::A, Keycode::Z, Keycode::E, Keycode::R],
+            [Keycode::Q, Keycode::S, Keycode::D, Keycode::F],
+            [Keycode::W, Keycode::X, Keycode::C, Keycode::V],
+        ]);
+    }
+
+    // Digits on the numpad digits, A to F on the operators
+    pub fn numpad() -> Self {
+        let mut map = Self::new();
+        let digits = [
+            Keycode::Kp0,
+            Keycode::Kp1,
+            Keycode::Kp2,
+            Keycode::Kp3,
+            Keycode::Kp4,
+            Keycode::Kp5,
+            Keycode::Kp6,
+            Keycode::Kp7,
+            Keycode::Kp8,
+            Keycode::Kp9,
+            Keycode::KpDivide,
+            Keycode::KpMultiply,
+            Keycode::KpMinus,
+            Keycode::KpPlus,
+            Keycode::KpEnter,
+            Keycode::KpPeriod,
+        ];
+        for (key, keycode) in digits.into_iter().enumerate() {
+            map.bind(keycode, key as u8);
+        }
+        return map;
+    }
+
+    // Directions of most games (2, 4, 6, 8) and 5 as action
+    pub fn arrows() -> Self {
+        let mut map = Self::new();
+        map.bind(Keycode::Up, KEY_2);
+        map.bind(Keycode::Left, KEY_4);
+        map.bind(Keycode::Right, KEY_6);
+        map.bind(Keycode::Down, KEY_8);
+        map.bind(Keycode::Space, KEY_5);
+        return map;
+    }
+
+    pub fn layout(name: &str) -> Option<Self> {
+        return match name {
+            "qwerty" => Some(Self::qwerty()),
+            "azerty" => Some(Self::azerty()),
+            "numpad" => Some(Self::numpad()),
+            "arrows" => Some(Self::arrows()),
+            _ => None,
+        };
+    }
+
+    fn from_rows(rows: [[Keycode; 4]; 4]) -> Self {
+        const KEYPAD: [[u8; 4]; 4] = [
+            [KEY_1, KEY_2, KEY_3, KEY_C],
+            [KEY_4, KEY_5, KEY_6, KEY_D],
+            [KEY_7, KEY_8, KEY_9, KEY_E],
+            [KEY_A, KEY_0, KEY_B, KEY_F],
+        ];
+        let mut map = Self::new();
+        for (row, keys) in rows.iter().zip(KEYPAD.iter()) {
+            for (keycode, key) in row.iter().zip(keys.iter()) {
+                map.bind(*keycode, *key);
+            }
+        }
+        return map;
+    }
+
+    // A host key presses a single Chip8 key, binding it again moves it
+    pub fn bind(&mut self, keycode: Keycode, key: u8) {
+        if let Some(previous) = self.keys.insert(keycode, key) {
+            self.keycodes[previous as usize].retain(|&bound| bound != keycode);
+        }
+        self.keycodes[key as usize].push(keycode);
+    }
+
+    pub fn extend(&mut self, other: &KeyMap) {
+        for (&keycode, &key) in other.keys.iter() {
+            self.bind(keycode, key);
+        }
+    }
+
+    pub fn key_for(&self, keycode: Keycode) -> Option<u8> {
+        return self.keys.get(&keycode).copied();
+    }
+
+    pub fn keycodes_for(&self, key: u8) -> impl Iterator<Item = Keycode> + '_ {
+        return self.keycodes[key as usize].iter().copied();
+    }
+
+    // Comma separated layout names and "<hex key>=<SDL key name>" bindings, added in order:
+    //   "qwerty,arrows,5=Return"
+    // or 16 SDL key names for the Chip8 keys 0 to F
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let items: Vec<&str> = text.split(',').map(|item| item.trim()).collect();
+        let mut map = Self::new();
+
+        let is_binding = |item: &&str| LAYOUTS.contains(item) || item.chars().nth(1) == Some('=');
+        if !items.iter().any(is_binding) {
+            if items.len() != KEY_COUNT {
+                return Err(format!("expected {} keys or layouts, got '{}'", KEY_COUNT, text));
+            }
+            for (key, name) in items.iter().enumerate() {
+                map.bind(parse_keycode(name)?, key as u8);
+            }
+            return Ok(map);
+        }
+
+        for item in items {
+            if let Some(layout) = Self::layout(item) {
+                map.extend(&layout);
+                continue;
+            }
+            let (key, name) = item
+                .split_once('=')
+                .ok_or_else(|| format!("unknown layout '{}', expected one of {}", item, LAYOUTS.join(", ")))?;
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| (key as usize) < KEY_COUNT)
+                .ok_or_else(|| format!("invalid Chip8 key '{}'", key))?;
+            map.bind(parse_keycode(name)?, key);
+        }
+        return Ok(map);
+    }
+}
+
+// SDL names are ASCII, other characters ("é") have keycodes that sdl2 cannot represent
+fn parse_keycode(name: &str) -> Result<Keycode, String> {
+    return Some(name)
+        .filter(|name| name.is_ascii())
+        .and_then(Keycode::from_name)
+        .ok_or_else(|| format!("unknown key '{}'", name));
+}
+
+#[cfg(test)]
+mod keymap_tests {
+    use super::*;
+
+    #[test]
+    fn given_sixteen_keys_when_parse_should_bind_keys_0_to_f_in_order() {
+        let map = KeyMap::parse("X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V").unwrap();
+
+        assert_eq!(map.key_for(Keycode::X), Some(KEY_0));
+        assert_eq!(map.key_for(Keycode::Num4), Some(KEY_C));
+        assert_eq!(map.key_for(Keycode::V), Some(KEY_F));
+    }
+
+    #[test]
+    fn given_layouts_when_parse_should_combine_them() {
+        let map = KeyMap::parse("qwerty,arrows").unwrap();
+
+        assert_eq!(map.key_for(Keycode::Q), Some(KEY_4));
+        assert_eq!(map.key_for(Keycode::Up), Some(KEY_2));
+        assert_eq!(map.keycodes_for(KEY_2).count(), 2);
+    }
+
+    #[test]
+    fn given_bindings_when_parse_should_add_them_to_the_layouts() {
+        let map = KeyMap::parse("qwerty,5=Return,f=Keypad 7").unwrap();
+
+        assert_eq!(map.key_for(Keycode::Return), Some(KEY_5));
+        assert_eq!(map.key_for(Keycode::Kp7), Some(KEY_F));
+        assert_eq!(map.key_for(Keycode::W), Some(KEY_5));
+    }
+
+    #[test]
+    fn given_rebound_host_key_when_keycodes_for_should_list_it_under_new_key_only() {
+        let mut map = KeyMap::qwerty();
+
+        map.bind(Keycode::Q, KEY_0);
+
+        assert_eq!(map.keycodes_for(KEY_4).count(), 0);
+        assert_eq!(map.keycodes_for(KEY_0).collect::<Vec<Keycode>>(), vec![Keycode::X, Keycode::Q]);
+    }
+
+    #[test]
+    fn given_multibyte_first_character_when_parse_should_fail_without_panic() {
+        assert!(KeyMap::parse("é,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V").is_err());
+        assert!(KeyMap::parse("é=Return").is_err());
+        assert!(KeyMap::parse("qwerty,2=é").is_err());
+    }
+
+    #[test]
+    fn given_azerty_when_layout_should_use_digits_for_the_number_row() {
+        let map = KeyMap::azerty();
+
+        assert_eq!(map.key_for(Keycode::Num2), Some(KEY_2));
+        assert_eq!(map.key_for(Keycode::A), Some(KEY_4));
+        assert_eq!(map.key_for(Keycode::W), Some(KEY_A));
+    }
+
+    #[test]
+    fn given_invalid_items_when_parse_should_fail() {
+        assert!(KeyMap::parse("qwerty,G=Return").is_err());
+        assert!(KeyMap::parse("qwerty,5=NoSuchKey").is_err());
+        assert!(KeyMap::parse("dvorak,5=Return").is_err());
+        assert!(KeyMap::parse("1,2,3").is_err());
+    }
+}
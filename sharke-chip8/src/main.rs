@@ -1,57 +1,273 @@
+mod cheat_menu;
+mod cli;
+mod commands;
+mod config;
+mod controller;
+mod keymap;
+mod debugger;
+mod gdb;
+mod palette;
+mod sdl2_audio;
 mod sdl2_drivers;
 
 use std::env;
-use chip8::cpu::Cpu;
-use chip8::threading::*;
-use chip8::timers::*;
+use chip8::audio::*;
+use chip8::constants::*;
+use chip8::cpu::PresentMode;
+use chip8::profiler::Profiler;
+use chip8::threading::MonotonicClock;
+use chip8::threading::Speed;
+use chip8::threading::Stopwatch;
+use chip8::trace::*;
+use chip8::driver::AudioSink;
+use cheat_menu::CheatMenu;
+use cli::*;
+use config::Config;
+use controller::*;
+use debugger::*;
+use gdb::GdbStub;
+use palette::*;
+use sdl2_audio::*;
 use sdl2_drivers::*;
 use std::cell::RefCell;
-use std::fs::read;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
 use std::path::Path;
 use std::rc::Rc;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::Mutex;
 
-const CPU_FREQ: f64 = 500.0;
-const TIMER_FREQ: f64 = 60.0;
+const PROFILE_HOT_SPOTS: usize = 20;
+const SPEEDS: [Speed; 7] = [
+    Speed::Multiplier(0.25),
+    Speed::Multiplier(0.5),
+    Speed::Multiplier(1.0),
+    Speed::Multiplier(2.0),
+    Speed::Multiplier(4.0),
+    Speed::Multiplier(8.0),
+    Speed::Unlimited,
+];
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    run(&args[1]);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Run(options) => run(*options),
+        Command::Disasm { rom_path, origin } => commands::disasm(&rom_path, origin),
+        Command::Asm {
+            source_path,
+            output_path,
+        } => commands::asm(&source_path, output_path.as_deref()),
+        Command::Info { rom_path } => commands::info(&rom_path),
+        Command::Bench {
+            rom_path,
+            seconds,
+            audio_out,
+            machine,
+        } => commands::bench(&rom_path, seconds, audio_out.as_deref(), &machine),
+        Command::Test {
+            rom_path,
+            frames,
+            expect,
+            audio_out,
+            machine,
+        } => commands::test(&rom_path, frames, expect.as_deref(), audio_out.as_deref(), &machine),
+        Command::TraceDiff { left_path, right_path } => diff_traces(&left_path, &right_path),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
 }
 
-fn run(rom_path: &String) {
-    let driver = Rc::new(RefCell::new(Sd2lDriver::new()));
+// Next speed up or down the list, the current speed is kept at both ends
+fn change_speed(speed: Speed, faster: bool) -> Speed {
+    let index = SPEEDS.iter().position(|value| *value == speed).unwrap_or(2);
+    if faster {
+        return SPEEDS[(index + 1).min(SPEEDS.len() - 1)];
+    }
+    return SPEEDS[index.saturating_sub(1)];
+}
 
-    let delay_timer = Rc::new(RefCell::new(CpuTimer::new()));
-    let sound_timer = Rc::new(RefCell::new(SoundTimer::new(TIMER_FREQ, driver.clone())));
-    let cpu = Rc::new(RefCell::new(Cpu::new(
-        delay_timer.clone(),
-        sound_timer.clone(),
-        driver.clone(),
-    )));
+fn speed_label(speed: Speed, paused: bool) -> String {
+    if paused {
+        return String::from("paused");
+    }
+    return match speed {
+        Speed::Multiplier(multiplier) => format!("{}x", multiplier),
+        Speed::Unlimited => String::from("unlimited"),
+    };
+}
 
-    let rom: Vec<u8> = read(rom_path).unwrap();
-    cpu.borrow_mut().load(rom);
-    println!("'{}' loaded", rom_path);
+fn diff_traces(left_path: &str, right_path: &str) -> Result<(), String> {
+    let open = |path: &str| File::open(path).map_err(|error| format!("cannot read '{}': {}", path, error));
+    let left = BufReader::new(open(left_path)?);
+    let right = BufReader::new(open(right_path)?);
 
-    let cpu_frequency = Frequency::new(CPU_FREQ, 1.0);
-    let timer_frequency = cpu_frequency.get_sub_frequency(TIMER_FREQ, 1.0);
-    let mut runner = ThreadRunner::new(
-        cpu_frequency,
-        vec![
-            Thread::new(cpu_frequency, cpu.clone()),
-            Thread::new(timer_frequency, delay_timer),
-            Thread::new(timer_frequency, sound_timer),
-        ],
-    );
-    runner.reset();
+    match first_divergence(left, right).map_err(|error| error.to_string())? {
+        None => println!("traces are identical"),
+        Some(divergence) => {
+            println!("traces diverge at line {} ({})", divergence.line, divergence.fields.join(", "));
+            println!("< {}", divergence.left.unwrap_or_else(|| String::from("<end of trace>")));
+            println!("> {}", divergence.right.unwrap_or_else(|| String::from("<end of trace>")));
+            std::process::exit(1);
+        }
+    }
+    return Ok(());
+}
 
-    let sdl_context = sdl2::init().unwrap();
+fn run(mut options: RunOptions) -> Result<(), String> {
+    let rom_path = options.rom_path.clone();
+    let rom = commands::read_rom(&rom_path)?;
+    let config = match &options.config_path {
+        Some(path) => Config::load(Path::new(path))?,
+        None => Config::load_default()?,
+    };
+    config
+        .settings_for(Path::new(&rom_path), &rom)
+        .apply(&mut options)
+        .map_err(|error| format!("config: {}", error))?;
+    // --immediate keeps presenting every draw, the erasing ones included
+    let anti_flicker = options.anti_flicker.unwrap_or_default();
+    if anti_flicker == AntiFlicker::NonErasing && options.present_mode == PresentMode::Frame {
+        options.present_mode = PresentMode::NonErasing;
+    }
+    // Phosphor fades out once per presented frame, immediate mode only presents on CLS and DRW
+    if matches!(anti_flicker, AntiFlicker::Phosphor(_)) && options.present_mode == PresentMode::Immediate {
+        return Err(String::from("--anti-flicker phosphor needs whole frames, it cannot be used with --immediate"));
+    }
+    let mut debugger = if options.debug {
+        let mut debugger = Debugger::new();
+        if let Some(script) = &options.debug_script {
+            debugger
+                .source(script)
+                .map_err(|error| format!("cannot read '{}': {}", script, error))?;
+        }
+        Some(debugger)
+    } else {
+        None
+    };
 
+    let sdl_context = sdl2::init().unwrap();
+    let mut driver = Sd2lDriver::new();
+    if let Some(key_map) = &options.key_map {
+        driver.set_key_map(key_map.clone());
+    }
     let rom_name = Path::new(&rom_path).file_name().unwrap().to_str().unwrap();
+    match sdl_context.game_controller() {
+        Ok(subsystem) => {
+            let map = options
+                .controller_map
+                .clone()
+                .unwrap_or_else(|| ControllerMap::for_rom(rom_name));
+            driver.attach_gamepads(Gamepads::new(subsystem, map));
+        }
+        Err(error) => println!("controller: {}", error),
+    }
+    let mut theme = options.theme.clone().unwrap_or_else(|| THEMES[0].to_string());
+    // Color overrides stay in place when the theme is cycled
+    let colors = [options.background, options.foreground, options.foreground2, options.blend];
+    driver.set_palette(Palette::theme(&theme).unwrap().with_colors(colors));
+    driver.set_anti_flicker(anti_flicker);
+    let driver = Rc::new(RefCell::new(driver));
+    let waveform = options.waveform.unwrap_or(Waveform::Square);
+    let volume = options.volume.unwrap_or(DEFAULT_VOLUME) as f32 / 100.0;
+    let mut audio: Vec<Box<dyn AudioSink>> = Vec::new();
+    if !options.mute.unwrap_or(false) {
+        let speakers = sdl_context
+            .audio()
+            .and_then(|subsystem| Sdl2Audio::new(&subsystem, waveform, volume, options.pitch));
+        match speakers {
+            Ok(speakers) => audio.push(Box::new(speakers)),
+            Err(error) => println!("audio: {}", error),
+        }
+    }
+    let recorder = options.audio_out.as_ref().map(|_| {
+        let mut recorder = Recorder::new(SAMPLE_RATE);
+        recorder.mixer_mut().set_waveform(waveform);
+        recorder.mixer_mut().set_volume(volume);
+        Rc::new(RefCell::new(recorder))
+    });
+    if let Some(recorder) = &recorder {
+        audio.push(Box::new(recorder.clone()));
+    }
+
+    let mut cheat_menu = CheatMenu::load(
+        Path::new(&rom_path),
+        &rom,
+        options.cheats_directory.as_ref().map(Path::new),
+    );
+    let mut emulator = options
+        .machine
+        .builder()
+        .present_mode(options.present_mode)
+        .rom(rom)
+        .video(Box::new(driver.clone()))
+        .audio(Box::new(audio))
+        .keypad(Box::new(driver.clone()))
+        .build()
+        .map_err(|error| format!("'{}' not loaded: {}", rom_path, error))?;
+    if let Some(speed) = options.speed {
+        emulator.machine_mut().set_speed(speed);
+    }
+    println!("'{}' loaded", rom_path);
+
+    // Output files are created before running, a wrong path must not lose the session
+    let audio_file = options.audio_out.as_deref().map(create_file).transpose()?;
+    let profile_file = options.profile_path.as_deref().map(create_file).transpose()?;
+    let profile_folded_file = options.profile_folded_path.as_deref().map(create_file).transpose()?;
+
+    if let Some(path) = &options.trace_path {
+        let file = create_file(path)?;
+        let tracer = TraceWriter::new(file, options.trace_filter.clone());
+        emulator.machine_mut().cpu_mut().attach_tracer(Box::new(tracer));
+    }
+
+    let profiler = if options.profile_path.is_some() || options.profile_folded_path.is_some() {
+        let profiler = Arc::new(Mutex::new(Profiler::new()));
+        emulator.machine_mut().cpu_mut().attach_tracer(Box::new(profiler.clone()));
+        Some(profiler)
+    } else {
+        None
+    };
+
+    let mut gdb = match options.gdb_port {
+        Some(port) => {
+            let stub = GdbStub::bind(port).map_err(|error| format!("gdb: cannot listen on port {}: {}", port, error))?;
+            println!("gdb: listening on localhost:{}", port);
+            Some(stub)
+        }
+        None => None,
+    };
+
+    if debugger.is_some() {
+        emulator.machine_mut().cpu_mut().halt();
+    }
 
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem.window(rom_name, SCREEN_W, SCREEN_H).build().unwrap();
+    let (width, height) = match options.scale {
+        Some(scale) => (scale * X_SIZE as u32, scale * Y_SIZE as u32),
+        None => (SCREEN_W, SCREEN_H),
+    };
+    let mut window = video_subsystem.window(rom_name, width, height);
+    if options.fullscreen.unwrap_or(false) {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|error| error.to_string())?;
     let mut canvas = window
         .into_canvas()
         .accelerated()
@@ -59,12 +275,18 @@ fn run(rom_path: &String) {
         .build()
         .unwrap();
 
-    let timer_subsystem = sdl_context.timer().unwrap();
-    let mut start_counter = timer_subsystem.ticks();
+    let mut stopwatch = Stopwatch::new(MonotonicClock::new());
 
     let mut event_pump = sdl_context.event_pump().unwrap();
     'main: loop {
+        let mut advance_frame = false;
         for event in event_pump.poll_iter() {
+            let machine = emulator.machine_mut();
+            // Keys typed in the open cheat menu are neither hotkeys nor Chip8 keys
+            if cheat_menu.handle_event(&event) {
+                continue;
+            }
+
             match event {
                 sdl2::event::Event::Quit { .. } => {
                     break 'main;
@@ -73,6 +295,19 @@ fn run(rom_path: &String) {
                     sdl2::keyboard::Keycode::Escape => {
                         break 'main;
                     }
+                    sdl2::keyboard::Keycode::P => machine.set_paused(!machine.is_paused()),
+                    sdl2::keyboard::Keycode::N => {
+                        machine.set_paused(true);
+                        advance_frame = true;
+                    }
+                    sdl2::keyboard::Keycode::Minus => machine.set_speed(change_speed(machine.speed(), false)),
+                    sdl2::keyboard::Keycode::Equals => machine.set_speed(change_speed(machine.speed(), true)),
+                    sdl2::keyboard::Keycode::Backspace => machine.set_speed(Speed::Multiplier(1.0)),
+                    sdl2::keyboard::Keycode::T => {
+                        theme = next_theme(&theme).to_string();
+                        println!("theme: {}", theme);
+                        driver.borrow_mut().set_palette(Palette::theme(&theme).unwrap().with_colors(colors));
+                    }
                     _ => {}
                 },
                 _ => {}
@@ -81,18 +316,75 @@ fn run(rom_path: &String) {
             driver.borrow_mut().pool_event(&event);
         }
 
-        let end_counter = timer_subsystem.ticks();
-        let elapsed = end_counter - start_counter;
+        let elapsed = stopwatch.lap();
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(emulator.machine_mut().cpu_mut());
+        }
+
+        if let Some(debugger) = debugger.as_mut() {
+            let event = debugger.poll(emulator.machine_mut().cpu_mut());
+            match event {
+                DebuggerEvent::Reset => {
+                    emulator.machine_mut().reset();
+                    emulator.machine_mut().cpu_mut().halt();
+                }
+                DebuggerEvent::Quit => break 'main,
+                DebuggerEvent::None => {}
+            }
+        }
+
+        if !cheat_menu.is_open() {
+            if advance_frame {
+                emulator.run_frame();
+            } else {
+                emulator.run_for(elapsed);
+            }
+        }
+        cheat_menu.apply(emulator.machine_mut().cpu_mut());
 
-        runner.tick(Duration::from_millis(elapsed as u64));
-        
         canvas.clear();
         driver.borrow_mut().draw(&mut canvas);
         canvas.present();        
 
-        let fps = 1000.0 / elapsed as f32;
-        let title = format!("{} - {} fps", rom_name, fps);
+        let fps = if elapsed.is_zero() { 0.0 } else { 1.0 / elapsed.as_secs_f32() };
+        let title = cheat_menu
+            .title()
+            .unwrap_or_else(|| {
+                let machine = emulator.machine();
+                let speed = speed_label(machine.speed(), machine.is_paused());
+                format!("{} - {} fps - {}", rom_name, fps, speed)
+            });
         canvas.window_mut().set_title(title.as_str()).unwrap();
-        start_counter = end_counter;
     }
+
+    if let (Some(path), Some(mut file), Some(recorder)) = (&options.audio_out, audio_file, recorder) {
+        recorder
+            .borrow()
+            .write_wav(&mut file)
+            .map_err(|error| write_error(path, error))?;
+    }
+
+    if let Some(profiler) = profiler {
+        let profiler = profiler.lock().unwrap();
+        if let (Some(path), Some(mut file)) = (&options.profile_path, profile_file) {
+            profiler
+                .write_report(&mut file, PROFILE_HOT_SPOTS)
+                .map_err(|error| write_error(path, error))?;
+        }
+        if let (Some(path), Some(mut file)) = (&options.profile_folded_path, profile_folded_file) {
+            profiler.write_folded(&mut file).map_err(|error| write_error(path, error))?;
+        }
+    }
+    return Ok(());
+}
+
+fn create_file(path: &str) -> Result<BufWriter<File>, String> {
+    return File::create(path)
+        .map(BufWriter::new)
+        .map_err(|error| format!("cannot create '{}': {}", path, error));
+}
+
+fn write_error(path: &str, error: std::io::Error) -> String {
+    return format!("cannot write '{}': {}", path, error);
 }
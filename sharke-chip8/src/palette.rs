@@ -0,0 +1,98 @@
+pub const THEMES: [&str; 6] = ["default", "green", "amber", "octo", "lcd", "high-contrast"];
+
+const fn rgb(value: u32) -> [u8; 4] {
+    let [_, red, green, blue] = value.to_be_bytes();
+    return [red, green, blue, 0xFF];
+}
+
+// RGBA colors indexed by the pixel value, bit n set when the pixel is on in plane n:
+// background, foreground (plane 1), XO-CHIP plane 2 and both planes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; 4],
+}
+
+impl Palette {
+    pub const fn new(background: u32, foreground: u32, foreground2: u32, blend: u32) -> Self {
+        return Self {
+            colors: [rgb(background), rgb(foreground), rgb(foreground2), rgb(blend)],
+        };
+    }
+
+    pub fn theme(name: &str) -> Option<Self> {
+        return match name {
+            "default" => Some(Self::new(0x000000, 0xFFFFFF, 0x808080, 0xC0C0C0)),
+            // Phosphor monitors, the second plane is a dimmer shade
+            "green" => Some(Self::new(0x001400, 0x33FF33, 0x1A8C1A, 0xB3FFB3)),
+            "amber" => Some(Self::new(0x140A00, 0xFFB000, 0x996A00, 0xFFE0A0)),
+            // Same colors as the Octo IDE
+            "octo" => Some(Self::new(0x996600, 0xFFCC00, 0xFF6600, 0x662200)),
+            // Dark pixels on a green LCD
+            "lcd" => Some(Self::new(0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230)),
+            "high-contrast" => Some(Self::new(0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF)),
+            _ => None,
+        };
+    }
+
+    // Colors given on the command line or in the config replace the theme ones
+    pub fn with_colors(mut self, colors: [Option<[u8; 4]>; 4]) -> Self {
+        for (color, replacement) in self.colors.iter_mut().zip(colors) {
+            *color = replacement.unwrap_or(*color);
+        }
+        return self;
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        return Self::theme(THEMES[0]).unwrap();
+    }
+}
+
+// Name of the theme following `name`, wrapping around
+pub fn next_theme(name: &str) -> &'static str {
+    let index = THEMES.iter().position(|theme| *theme == name).map_or(0, |index| index + 1);
+    return THEMES[index % THEMES.len()];
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::next_theme;
+    use super::Palette;
+    use super::THEMES;
+
+    #[test]
+    fn given_every_theme_name_when_theme_should_find_a_palette() {
+        for name in THEMES {
+            assert!(Palette::theme(name).is_some(), "{}", name);
+        }
+        assert_eq!(Palette::theme("sepia"), None);
+    }
+
+    #[test]
+    fn given_theme_when_theme_should_convert_colors_to_opaque_rgba() {
+        let palette = Palette::theme("green").unwrap();
+
+        assert_eq!(palette.colors[0], [0x00, 0x14, 0x00, 0xFF]);
+        assert_eq!(palette.colors[1], [0x33, 0xFF, 0x33, 0xFF]);
+    }
+
+    #[test]
+    fn given_colors_when_with_colors_should_only_replace_given_ones() {
+        let palette = Palette::default().with_colors([None, Some([1, 2, 3, 0xFF]), None, None]);
+
+        assert_eq!(palette.colors[0], Palette::default().colors[0]);
+        assert_eq!(palette.colors[1], [1, 2, 3, 0xFF]);
+    }
+
+    #[test]
+    fn given_last_theme_when_next_theme_should_wrap_around() {
+        assert_eq!(next_theme("default"), "green");
+        assert_eq!(next_theme(THEMES[THEMES.len() - 1]), THEMES[0]);
+    }
+
+    #[test]
+    fn given_unknown_theme_when_next_theme_should_start_at_first() {
+        assert_eq!(next_theme("sepia"), THEMES[0]);
+    }
+}
@@ -0,0 +1,66 @@
+use chip8::audio::*;
+use chip8::driver::AudioSink;
+use chip8::timers::AudioPattern;
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioDevice;
+use sdl2::audio::AudioSpecDesired;
+use std::time::Duration;
+
+struct Output {
+    mixer: Mixer,
+}
+
+// Runs on the SDL audio thread, the emulation only updates the mixer under the device lock
+impl AudioCallback for Output {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.mixer.render(out);
+    }
+}
+
+pub struct Sdl2Audio {
+    device: AudioDevice<Output>,
+    pitch: Option<u32>,
+}
+
+impl Sdl2Audio {
+    // Pitch overrides the frequency requested by the core, volume goes from 0 to 1
+    pub fn new(
+        audio_subsystem: &sdl2::AudioSubsystem,
+        waveform: Waveform,
+        volume: f32,
+        pitch: Option<u32>,
+    ) -> Result<Self, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired, |spec| {
+            let mut mixer = Mixer::new(spec.freq as u32);
+            mixer.set_waveform(waveform);
+            mixer.set_volume(volume);
+            Output { mixer: mixer }
+        })?;
+        device.resume();
+        return Ok(Self {
+            device: device,
+            pitch: pitch,
+        });
+    }
+}
+
+impl AudioSink for Sdl2Audio {
+    fn tone_on(&mut self, frequency: u32) {
+        self.device.lock().mixer.tone_on(self.pitch.unwrap_or(frequency));
+    }
+
+    fn tone_off(&mut self, length: Duration) {
+        self.device.lock().mixer.tone_off(length);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.device.lock().mixer.set_pattern(pattern);
+    }
+}
@@ -1,12 +1,51 @@
 use chip8::constants::*;
 use chip8::driver::*;
+use chip8::framebuffer::Framebuffer;
+use crate::controller::*;
+use crate::keymap::*;
+use crate::palette::Palette;
 use std::collections::HashMap;
 
 pub const SCREEN_W: u32 = 800;
 pub const SCREEN_H: u32 = 600;
+pub const DEFAULT_PHOSPHOR_FRAMES: u32 = 5;
+
+// Reduces the flicker of sprites erased and drawn again with XOR
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum AntiFlicker {
+    #[default]
+    Off,
+    // Turned off pixels fade out over the given number of frames
+    Phosphor(u32),
+    // A pixel is on when it is on in the current or the previous frame
+    Blend,
+    // Presents the display after the DRW that erased no pixel, see PresentMode::NonErasing
+    NonErasing,
+}
+
+impl AntiFlicker {
+    // "off", "phosphor", "phosphor:<frames>", "blend" or "non-erasing"
+    pub fn parse(text: &str) -> Option<Self> {
+        return match text.split_once(':') {
+            Some(("phosphor", frames)) => frames.parse().ok().filter(|&frames| frames > 0).map(AntiFlicker::Phosphor),
+            Some(_) => None,
+            None => match text {
+                "off" => Some(AntiFlicker::Off),
+                "phosphor" => Some(AntiFlicker::Phosphor(DEFAULT_PHOSPHOR_FRAMES)),
+                "blend" => Some(AntiFlicker::Blend),
+                "non-erasing" => Some(AntiFlicker::NonErasing),
+                _ => None,
+            },
+        };
+    }
+}
 
-const COLOR_0: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
-const COLOR_1: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+// Shown value of a pixel, with its brightness between 0 and 1 while it fades out
+#[derive(Copy, Clone)]
+struct Pixel {
+    value: u8,
+    brightness: f32,
+}
 
 struct InputState {
     state: HashMap<sdl2::keyboard::Keycode, bool>,
@@ -33,71 +72,89 @@ impl InputState {
 }
 
 pub struct Sd2lDriver {
-    key_mappings: HashMap<u8, sdl2::keyboard::Keycode>,
+    key_map: KeyMap,
+    gamepads: Option<Gamepads>,
     input_state: InputState,
+    palette: Palette,
+    anti_flicker: AntiFlicker,
+    pixels: Vec<Pixel>,
+    previous: Vec<u8>,
     pub buffer: Vec<u8>,
 }
 
 impl Sd2lDriver {
     pub fn new() -> Self {
-        let mut key_mappings = HashMap::new();
-        key_mappings.insert(KEY_1, sdl2::keyboard::Keycode::Kp1);
-        key_mappings.insert(KEY_2, sdl2::keyboard::Keycode::Kp2);
-        key_mappings.insert(KEY_3, sdl2::keyboard::Keycode::Kp3);
-        key_mappings.insert(KEY_C, sdl2::keyboard::Keycode::Kp4);
-        key_mappings.insert(KEY_4, sdl2::keyboard::Keycode::Q);
-        key_mappings.insert(KEY_5, sdl2::keyboard::Keycode::W);
-        key_mappings.insert(KEY_6, sdl2::keyboard::Keycode::E);
-        key_mappings.insert(KEY_D, sdl2::keyboard::Keycode::R);
-        key_mappings.insert(KEY_7, sdl2::keyboard::Keycode::A);
-        key_mappings.insert(KEY_8, sdl2::keyboard::Keycode::S);
-        key_mappings.insert(KEY_9, sdl2::keyboard::Keycode::D);
-        key_mappings.insert(KEY_E, sdl2::keyboard::Keycode::F);
-        key_mappings.insert(KEY_A, sdl2::keyboard::Keycode::Z);
-        key_mappings.insert(KEY_0, sdl2::keyboard::Keycode::X);
-        key_mappings.insert(KEY_B, sdl2::keyboard::Keycode::C);
-        key_mappings.insert(KEY_F, sdl2::keyboard::Keycode::V);
-
         return Self {
-            key_mappings: key_mappings,
+            key_map: KeyMap::qwerty(),
+            gamepads: None,
             input_state: InputState::new(),
+            palette: Palette::default(),
+            anti_flicker: AntiFlicker::Off,
+            pixels: vec![Pixel { value: 0, brightness: 0.0 }; X_SIZE * Y_SIZE],
+            previous: vec![0; X_SIZE * Y_SIZE],
             buffer: vec![0; 4 * X_SIZE * Y_SIZE],
         };
     }
 
+    pub fn set_key_map(&mut self, key_map: KeyMap) {
+        self.key_map = key_map;
+        self.input_state = InputState::new();
+    }
+
+    pub fn attach_gamepads(&mut self, gamepads: Gamepads) {
+        self.gamepads = Some(gamepads);
+    }
+
+    // The shown pixels are converted again right away, even while paused
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        for y in 0..Y_SIZE {
+            self.convert_row(y);
+        }
+    }
+
+    pub fn set_anti_flicker(&mut self, anti_flicker: AntiFlicker) {
+        self.anti_flicker = anti_flicker;
+    }
+
     pub fn pool_event(&mut self, event: &sdl2::event::Event) {
+        if let Some(gamepads) = self.gamepads.as_mut() {
+            gamepads.handle_event(event);
+        }
+
         match event {
-            sdl2::event::Event::KeyDown { keycode, .. } => {
-                let key = keycode.unwrap();
-                let value = self.find_key_for_value(key);
-                if value.is_some() {
-                    self.input_state.set(key, true);
-                }
-            }
-            sdl2::event::Event::KeyUp { keycode, .. } => {
-                let key = keycode.unwrap();
-                let value = self.find_key_for_value(key);
-                if value.is_some() {
-                    self.input_state.set(key, false);
-                }
-            }
+            sdl2::event::Event::KeyDown {
+                keycode: Some(keycode), ..
+            } if self.key_map.key_for(*keycode).is_some() => self.input_state.set(*keycode, true),
+            sdl2::event::Event::KeyUp {
+                keycode: Some(keycode), ..
+            } if self.key_map.key_for(*keycode).is_some() => self.input_state.set(*keycode, false),
             _ => {}
         }
     }
 
-    fn find_key_for_value(&self, value: sdl2::keyboard::Keycode) -> Option<&u8> {
-        return self
-            .key_mappings
-            .iter()
-            .find_map(|(key, &val)| if val == value { Some(key) } else { None });
-    }
-
     fn copy_color(&mut self, index: usize, color: &[u8]) {
         for i in 0..=3 {
             self.buffer[index + i] = color[i];
         }
     }
 
+    // Fading pixels are mixed with the background
+    fn convert_row(&mut self, y: usize) {
+        let background = self.palette.colors[0];
+        for x in 0..X_SIZE {
+            let pixel = self.pixels[y * X_SIZE + x];
+            let foreground = self.palette.colors[pixel.value as usize & 3];
+            let mut color = [0; 4];
+            for (channel, value) in color.iter_mut().enumerate() {
+                let mixed = background[channel] as f32
+                    + (foreground[channel] as f32 - background[channel] as f32) * pixel.brightness;
+                *value = mixed.round() as u8;
+            }
+            self.copy_color(4 * (y * X_SIZE + x), &color);
+        }
+    }
+
     pub fn draw(&mut self, canvas: &mut sdl2::render::WindowCanvas) {
         let buffer = self.buffer.as_mut_slice();
         let surface = sdl2::surface::Surface::from_data(
@@ -114,11 +171,12 @@ impl Sd2lDriver {
             .create_texture_from_surface(surface)
             .unwrap();
 
-        let screen_rect = sdl2::rect::Rect::new(0, 0, SCREEN_W, SCREEN_H);
-        let zoom = (SCREEN_H / Y_SIZE as u32).min(SCREEN_W / X_SIZE as u32) as i32;
+        let (screen_w, screen_h) = canvas.output_size().unwrap();
+        let screen_rect = sdl2::rect::Rect::new(0, 0, screen_w, screen_h);
+        let zoom = (screen_h / Y_SIZE as u32).min(screen_w / X_SIZE as u32) as i32;
         let texture_rect = sdl2::rect::Rect::new(
-            (SCREEN_W as i32 - zoom * X_SIZE as i32) / 2,
-            (SCREEN_H as i32 - zoom * Y_SIZE as i32) / 2,
+            (screen_w as i32 - zoom * X_SIZE as i32) / 2,
+            (screen_h as i32 - zoom * Y_SIZE as i32) / 2,
             zoom as u32 * X_SIZE as u32,
             zoom as u32 * Y_SIZE as u32,
         );
@@ -127,50 +185,50 @@ impl Sd2lDriver {
     }
 }
 
-impl Driver for Sd2lDriver {
-    #[cfg(target_os = "windows")]
-    fn sound_do_beep(&mut self, frequency: u32, duration: u32) {
-        winconsole::console::beep(frequency, duration);
-    }
-
-    #[cfg(not(target_os = "windows"))]
-    fn sound_do_beep(&mut self, _frequency: u32, _duration: u32) {
-    }
-
-    fn video_fill_buffer(&mut self, display: &Vec<Vec<usize>>) {
-        let mut index = 0;
-        for y in 0..Y_SIZE {
+impl VideoSink for Sd2lDriver {
+    // Only the rows that changed since the last frame are converted, the blending modes convert every row
+    fn fill_buffer(&mut self, framebuffer: &Framebuffer) {
+        let rows: Vec<usize> = match self.anti_flicker {
+            AntiFlicker::Phosphor(_) | AntiFlicker::Blend => (0..Y_SIZE).collect(),
+            AntiFlicker::Off | AntiFlicker::NonErasing => framebuffer.dirty_rows().collect(),
+        };
+        for y in rows {
             for x in 0..X_SIZE {
-                self.copy_color(
-                    index,
-                    if display[x][y] == 0 {
-                        &COLOR_0
-                    } else {
-                        &COLOR_1
-                    },
-                );
-                index += 4;
+                let index = y * X_SIZE + x;
+                let value = framebuffer.pixel(x, y);
+                let pixel = &mut self.pixels[index];
+                match self.anti_flicker {
+                    AntiFlicker::Phosphor(frames) if value == 0 => {
+                        pixel.brightness = (pixel.brightness - 1.0 / frames as f32).max(0.0);
+                    }
+                    AntiFlicker::Blend => {
+                        *pixel = Pixel { value: value | self.previous[index], brightness: 1.0 };
+                        self.previous[index] = value;
+                    }
+                    _ => *pixel = Pixel { value: value, brightness: 1.0 },
+                }
             }
+            self.convert_row(y);
         }
     }
+}
 
-    fn input_is_key_down(&mut self, keycode: u8) -> bool {
-        let mapping = self.key_mappings.get(&keycode).unwrap();
-        return self.input_state.get(&mapping);
+impl Keypad for Sd2lDriver {
+    // Down while any of its host keys or controller inputs is held
+    fn is_key_down(&mut self, keycode: u8) -> bool {
+        let gamepad_down = self.gamepads.as_ref().is_some_and(|gamepads| gamepads.is_key_down(keycode));
+        return gamepad_down
+            || self
+                .key_map
+                .keycodes_for(keycode)
+                .any(|mapping| self.input_state.get(&mapping));
     }
 
-    fn input_is_key_up(&mut self, keycode: u8) -> bool {
-        let mapping = self.key_mappings.get(&keycode).unwrap();
-        return !self.input_state.get(&mapping);
+    fn is_key_up(&mut self, keycode: u8) -> bool {
+        return !self.is_key_down(keycode);
     }
 
-    fn input_is_any_key_down(&mut self, keycode: &mut u8) -> bool {
-        for (key, val) in self.key_mappings.iter() {
-            if self.input_state.get(&val) {
-                *keycode = *key;
-                return true;
-            }
-        }
-        return false;
+    fn any_key_down(&mut self) -> Option<u8> {
+        return (0..KEY_COUNT as u8).find(|&key| self.is_key_down(key));
     }
 }